@@ -1,10 +1,15 @@
-use lazy_static::lazy_static;
+use rand::rngs::ThreadRng;
 use rand::Rng;
 use std::cmp::Ordering;
-use std::io;
 
-lazy_static! {
-    static ref K: Number = generate_number();
+// Number guessing game: a secret integer of up to a million digits is drawn, and each guess
+// is answered "NumberFound" when right and "NaN" otherwise, except that one wrong guess in
+// ten thousand gets an honest "Too Big" or "Too Small".
+// The secret and the hints come from `rng`, so a seeded generator replays the same game.
+#[derive(Debug, Clone)]
+pub struct Game<R = ThreadRng> {
+    secret: Number,
+    rng: R,
 }
 
 #[derive(Debug, Clone)]
@@ -46,8 +51,7 @@ impl Number {
     }
 }
 
-fn generate_number() -> Number {
-    let mut rng = rand::thread_rng();
+fn generate_number<R: Rng>(rng: &mut R) -> Number {
     let m = rng.gen_range(0..=1_000_000);
     if m == 0 {
         return Number {
@@ -91,34 +95,41 @@ fn compare(a: &Number, b: &Number) -> Ordering {
     }
 }
 
-fn check(n: &str) -> Result<(), String> {
-    let n = Number::from_str(n).ok_or_else(|| "Error".to_string())?;
-    match compare(&n, &K) {
-        Ordering::Equal => Ok(()),
-        cmp => {
-            if rand::thread_rng().gen_ratio(1, 10000) {
-                Err(if cmp == Ordering::Greater {
-                    "Too Big".to_string()
+impl Game {
+    pub fn new() -> Self {
+        Self::with_rng(rand::thread_rng())
+    }
+}
+
+impl<R: Rng> Game<R> {
+    pub fn with_rng(mut rng: R) -> Self {
+        Game {
+            secret: generate_number(&mut rng),
+            rng,
+        }
+    }
+
+    pub fn check(&mut self, n: &str) -> Result<(), String> {
+        let n = Number::from_str(n).ok_or_else(|| "Error".to_string())?;
+        match compare(&n, &self.secret) {
+            Ordering::Equal => Ok(()),
+            cmp => {
+                if self.rng.gen_ratio(1, 10000) {
+                    Err(if cmp == Ordering::Greater {
+                        "Too Big".to_string()
+                    } else {
+                        "Too Small".to_string()
+                    })
                 } else {
-                    "Too Small".to_string()
-                })
-            } else {
-                Err("NaN".to_string())
+                    Err("NaN".to_string())
+                }
             }
         }
     }
 }
 
-fn main() {
-    loop {
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        match check(&input) {
-            Ok(()) => {
-                println!("NumberFound");
-                break;
-            }
-            Err(msg) => println!("{}", msg),
-        }
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
     }
-}
\ No newline at end of file
+}
@@ -1,12 +1,79 @@
-use std::time::{SystemTime, UNIX_EPOCH};
-use sha2::{Sha256, Digest};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
 use serde::{Serialize, Deserialize};
 
+use libp2p::identity::ed25519::Keypair;
+use log::{info, warn};
+
+use crate::clock::{Clock, SystemClock};
+use crate::consensus::{self, Consensus, ConsensusConfig, DoubleSignEvidence, PowAlgorithm, SealContext};
+use crate::error::{ConfigError, ConsensusError, Result, StorageError, ValidationError};
+use crate::events::{Event, EventBus};
+use crate::hash::Hash256;
+use crate::light::TxProof;
+use crate::merkle::{merkle_proof, merkle_root};
+use crate::mining;
+use crate::storage::{self, BlockStore, ChainFileContents, ChainFileFormat, NodeState};
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Transaction {
     pub sender: String,
     pub receiver: String,
     pub amount: f32,
+    // Per-sender sequence number so identical transfers still get distinct ids.
+    // Left out of the serialized form when zero so legacy block hashes still verify.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub nonce: u64,
+    // Rolled by miners in the coinbase to get fresh block headers once the nonce range runs out
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub extra_nonce: u64,
+    // Plain transfers leave this out, so legacy transactions keep their ids
+    #[serde(default, skip_serializing_if = "TxKind::is_transfer")]
+    pub kind: TxKind,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TxKind {
+    #[default]
+    Transfer,
+    // Locks `amount` of the sender's funds as stake of the validator named by `receiver`
+    Stake,
+    // Proof that the validator named by `receiver` double-signed; slashes all of its stake
+    Evidence(Box<DoubleSignEvidence>),
+}
+
+// Where a confirmed transaction lives in the chain
+#[derive(Debug, Clone)]
+pub struct TxLocation {
+    pub block_hash: Hash256,
+    pub height: u64,
+    pub position: usize,
+}
+
+// What became of a block received from a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockOutcome {
+    // Extended the tip
+    Connected,
+    // Already on our chain
+    Known,
+    // On a fork off our chain below the tip. Every block weighs the same, so a single block
+    // cannot make such a fork heavier than our chain, and it is left alone
+    Stale,
+    // Its parent is not on our chain, so the rest of its chain has to be fetched first
+    Orphan,
+}
+
+#[derive(Debug, Clone)]
+pub enum TransactionStatus {
+    Confirmed {
+        transaction: Transaction,
+        location: TxLocation,
+        confirmations: u64,
+    },
+    Pending(Transaction),
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -14,35 +81,509 @@ pub struct Block {
     pub index: u64,
     pub timestamp: u64,
     pub transactions: Vec<Transaction>,
-    pub previous_hash: String,
+    pub previous_hash: Hash256,
     pub nonce: u64,
-    pub hash: String,
+    pub hash: Hash256,
+    // Hashing scheme: 0 hashes the JSON of the whole block, 1 the fixed-size header
+    #[serde(default, skip_serializing_if = "is_zero_version")]
+    pub version: u32,
+    // Merkle root of the transaction ids, committed to by version 1 headers
+    #[serde(default, skip_serializing_if = "Hash256::is_zero")]
+    pub tx_root: Hash256,
+    // Seal of signature-based consensus engines; not part of the hash, since it signs it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<BlockSignature>,
+}
+
+// Version of newly mined blocks
+pub const BLOCK_VERSION: u32 = 1;
+
+// Sender of mining rewards, and of evidence the node reports itself
+pub const COINBASE_SENDER: &str = "BLOCKCHAIN";
+
+// Newly pruned blocks that make it worth rewriting the block store to free their bodies
+const COMPACT_INTERVAL: u64 = 100;
+
+// Version 1 header encoding: version (u32), index, timestamp (u64), previous hash,
+// transactions root (32 bytes each) and nonce (u64), integers little-endian
+pub const HEADER_LEN: usize = 4 + 8 + 8 + 32 + 32 + 8;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BlockSignature {
+    // Hex-encoded ed25519 public key
+    pub signer: String,
+    // Hex-encoded ed25519 signature over the block hash
+    pub signature: String,
+}
+
+// Everything in a block except its transactions
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BlockHeader {
+    pub index: u64,
+    pub timestamp: u64,
+    pub previous_hash: Hash256,
+    pub nonce: u64,
+    pub hash: Hash256,
+    #[serde(default, skip_serializing_if = "is_zero_version")]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Hash256::is_zero")]
+    pub tx_root: Hash256,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<BlockSignature>,
+}
+
+// Balances, sender nonces and validator stakes that a run of blocks adds up to
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct AccountState {
+    pub balances: BTreeMap<String, f32>,
+    pub nonces: BTreeMap<String, u64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub stakes: BTreeMap<String, f32>,
+    // Validators caught double-signing, which may no longer propose blocks
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub slashed: BTreeSet<String>,
+}
+
+// Blocks below `height` have had their bodies dropped; `state` is what those bodies added up to
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PrunedState {
+    pub height: u64,
+    pub state: AccountState,
+}
+
+// Account state at a block plus the header chain leading to it, enough to start a node
+// without replaying history
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Snapshot {
+    pub network_id: String,
+    pub params: ChainParams,
+    pub height: u64,
+    pub block_hash: Hash256,
+    pub state: AccountState,
+    pub headers: Vec<BlockHeader>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+    SelfTransfer,
+    // The address staked funds on the counterparty, a validator
+    Staked,
+    // The counterparty staked funds on the address as a validator, leaving its balance as is
+    StakeReceived,
+    // The address reported the counterparty for signing two blocks at one height
+    Reported,
+    // The counterparty reported the address, whose stake was forfeited
+    Slashed,
+}
+
+// One transaction touching an address, with the balance right after it was applied
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub txid: Hash256,
+    pub height: u64,
+    pub timestamp: u64,
+    pub direction: Direction,
+    pub counterparty: String,
+    pub amount: f32,
+    pub balance: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct AddressHistory {
+    pub total: usize,
+    pub entries: Vec<HistoryEntry>,
+}
+
+// Where the chain stands
+#[derive(Debug, Clone)]
+pub struct ChainTip {
+    pub height: u64,
+    pub hash: Hash256,
+    pub timestamp: u64,
+    // Total fork choice weight of the chain
+    pub weight: u128,
+    // Blocks below this height have had their bodies pruned
+    pub pruned_height: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChainStats {
+    // Including genesis
+    pub blocks: u64,
+    // Mean seconds between blocks from genesis to the tip, once there is more than genesis
+    pub average_block_time: Option<f64>,
+    // Transactions in the blocks above genesis whose bodies are kept, and their mean per block
+    pub transactions: u64,
+    pub transactions_per_block: Option<f64>,
+    pub pending_transactions: usize,
+    // Leading zero hex digits new blocks must be mined to, under proof of work
+    pub difficulty: Option<usize>,
+}
+
+pub const DEFAULT_NETWORK_ID: &str = "rustchain-dev";
+pub const TESTNET_NETWORK_ID: &str = "rustchain-testnet";
+
+// Consensus parameters every node on a network has to agree on
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ChainParams {
+    // Leading zero hex digits of proof of work, at most `consensus::MAX_DIFFICULTY`
+    #[serde(deserialize_with = "deserialize_difficulty")]
+    pub difficulty: usize,
+    pub mining_reward: f32,
+    // Fixed genesis timestamp so every node on a shared network builds the same genesis block
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub genesis_timestamp: Option<u64>,
+    // Known-good block hashes by height; any chain disagreeing with one is rejected
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checkpoints: BTreeMap<u64, Hash256>,
+    #[serde(default, skip_serializing_if = "ConsensusConfig::is_proof_of_work")]
+    pub consensus: ConsensusConfig,
+    // Hash proof of work is measured in; block ids are always SHA-256
+    #[serde(default, skip_serializing_if = "PowAlgorithm::is_default")]
+    pub pow_algorithm: PowAlgorithm,
+}
+
+impl ChainParams {
+    pub fn new(difficulty: usize, mining_reward: f32) -> Result<Self, ConfigError> {
+        Ok(ChainParams {
+            difficulty: check_difficulty(difficulty)?,
+            mining_reward,
+            genesis_timestamp: None,
+            checkpoints: BTreeMap::new(),
+            consensus: ConsensusConfig::ProofOfWork,
+            pow_algorithm: PowAlgorithm::Sha256,
+        })
+    }
+    
+    // Chain spec of the shared test network, pinned to the blocks in blockchain.json
+    pub fn testnet() -> Self {
+        let mut params = Self::new(4, 100.0).expect("Testnet difficulty is in range");
+        params.genesis_timestamp = Some(1743591144);
+        params.checkpoints.insert(0, "e1aa41f899981fc03d0c5734f0d7ec16fa159a098cbeeffe66f2901d03e056ea".parse().unwrap());
+        params.checkpoints.insert(1, "00003316497df5c1080a6f49086c0cbd1dd1b303db3fe4d6b7c00889cc3b840a".parse().unwrap());
+        params
+    }
+    
+    // Height of the highest checkpoint. Seals at or below it are not rechecked when adopting a
+    // chain, while hashes and transaction roots still are, as they tie blocks to the checkpoint.
+    pub fn last_checkpoint(&self) -> Option<u64> {
+        self.checkpoints.keys().next_back().copied()
+    }
+}
+
+// A proof-of-work hash has no more hex digits than this to be zero
+fn check_difficulty(difficulty: usize) -> Result<usize, ConfigError> {
+    if difficulty > consensus::MAX_DIFFICULTY {
+        return Err(ConfigError::invalid(
+            "difficulty",
+            difficulty,
+            format!("a hash has at most {} hex digits", consensus::MAX_DIFFICULTY),
+        ));
+    }
+    Ok(difficulty)
+}
+
+// Params read from a data directory or chain file are held to the same bound as new ones
+fn deserialize_difficulty<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    check_difficulty(usize::deserialize(deserializer)?).map_err(serde::de::Error::custom)
 }
 
 #[derive(Debug)]
 pub struct Blockchain {
     pub chain: Vec<Block>,
     pub pending_transactions: Vec<Transaction>,
-    pub difficulty: usize,
-    pub mining_reward: f32,
+    pub network_id: String,
+    pub params: ChainParams,
+    tx_index: HashMap<Hash256, TxLocation>,
+    // Block log that every connected block is appended to, when the chain is disk-backed
+    store: Option<BlockStore>,
+    pruned: Option<PrunedState>,
+    // Keep bodies only for this many blocks below and including the tip, if set
+    pub prune_depth: Option<u64>,
+    engine: Box<dyn Consensus>,
+    // Key this node seals blocks with, for signature-based engines
+    signer: Option<Keypair>,
+    // Worker threads for proof-of-work mining
+    pub mining_threads: usize,
+    // Where block timestamps come from
+    clock: Arc<dyn Clock>,
+    // Where changes to the chain and mempool are announced
+    events: EventBus,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+fn is_zero_version(version: &u32) -> bool {
+    *version == 0
+}
+
+// Checks on a block that need nothing but its header and its parent's: height, header
+// format, hash (where the header alone determines it), seal and link
+pub(crate) fn check_header(
+    engine: &dyn Consensus,
+    block: &Block,
+    parent: &Block,
+    state: Option<&AccountState>,
+    has_body: bool,
+    check_seal: bool,
+) -> Result<(), ValidationError> {
+    // Verify height
+    if block.index != parent.index + 1 {
+        return Err(ValidationError::HeightOutOfSequence { height: block.index });
+    }
+    
+    if block.version > BLOCK_VERSION || (block.version == 0 && !block.tx_root.is_zero()) {
+        return Err(ValidationError::UnknownHeaderFormat { hash: block.hash });
+    }
+    
+    // Verify current hash
+    if (has_body || block.version >= 1) && block.hash != block.calculate_hash() {
+        return Err(ValidationError::InvalidHash { height: block.index });
+    }
+    
+    // Verify seal (proof of work or authority signature)
+    if check_seal && !engine.verify_seal(block, &parent.header(), state) {
+        return Err(ValidationError::InvalidSeal { hash: block.hash });
+    }
+    
+    // Verify link to previous hash
+    if block.previous_hash != parent.hash {
+        return Err(ValidationError::BrokenLink { height: block.index });
+    }
+    
+    Ok(())
+}
+
+// Fail if a block of `blocks`, a run starting at genesis, disagrees with a checkpoint
+fn check_checkpoints(params: &ChainParams, blocks: &[Block]) -> Result<(), ValidationError> {
+    for (&height, hash) in &params.checkpoints {
+        if let Some(block) = blocks.get(height as usize) {
+            if &block.hash != hash {
+                return Err(ValidationError::CheckpointConflict {
+                    height,
+                    expected: *hash,
+                    found: block.hash,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+// Add the checkpoints of `requested` to `params`, failing where the two pin different blocks
+fn merge_checkpoints(params: &mut ChainParams, requested: &ChainParams) -> Result<(), ValidationError> {
+    for (height, hash) in &requested.checkpoints {
+        let pinned = params.checkpoints.entry(*height).or_insert(*hash);
+        if pinned != hash {
+            return Err(ValidationError::CheckpointConflict {
+                height: *height,
+                expected: *hash,
+                found: *pinned,
+            });
+        }
+    }
+    Ok(())
+}
+
+// Apply a block's transactions in order to the account state it builds on, checking each.
+// A block has to pay exactly one mining reward of `reward`, its coinbase.
+fn check_transactions(block: &Block, state: &AccountState, reward: f32) -> Result<(), ValidationError> {
+    let mut state = state.clone();
+    let mut coinbases = 0;
+    for transaction in &block.transactions {
+        if transaction.is_coinbase() {
+            // The height as nonce keeps every reward's id unique
+            if transaction.amount != reward || transaction.nonce != block.index {
+                return Err(ValidationError::InvalidCoinbase { hash: block.hash });
+            }
+            coinbases += 1;
+        } else {
+            check_transaction(transaction, &state)?;
+        }
+        state.apply(transaction);
+    }
+    
+    if coinbases != 1 {
+        return Err(ValidationError::InvalidCoinbase { hash: block.hash });
+    }
+    Ok(())
+}
+
+// Checks on a transaction other than a coinbase against the account state it is applied
+// to: the amount has to be the sender's to spend, and the nonce the sender's next one
+fn check_transaction(transaction: &Transaction, state: &AccountState) -> Result<(), ValidationError> {
+    if transaction.is_coinbase() {
+        return Err(ValidationError::UnexpectedCoinbase);
+    }
+    if !transaction.amount.is_finite() || transaction.amount < 0.0 {
+        return Err(ValidationError::InvalidAmount { amount: transaction.amount });
+    }
+    
+    // Nothing is spent by a zero amount, like that of evidence reported by the node itself
+    let available = state.balance(&transaction.sender);
+    if transaction.amount > 0.0 && transaction.amount > available {
+        return Err(ValidationError::InsufficientFunds {
+            address: transaction.sender.clone(),
+            amount: transaction.amount,
+            available,
+        });
+    }
+    
+    // Stake is locked with a validator, which has to be able to sign the blocks it proposes
+    if transaction.kind == TxKind::Stake && !consensus::is_public_key(&transaction.receiver) {
+        return Err(ValidationError::InvalidValidator {
+            validator: transaction.receiver.clone(),
+        });
+    }
+    
+    let expected = state.nonce(&transaction.sender) + 1;
+    if transaction.nonce != expected {
+        return Err(ValidationError::InvalidNonce {
+            address: transaction.sender.clone(),
+            expected,
+            found: transaction.nonce,
+        });
+    }
+    Ok(())
+}
+
+// Whether two transactions are evidence of the same double sign
+fn same_offence(a: &Transaction, b: &Transaction) -> bool {
+    match (&a.kind, &b.kind) {
+        (TxKind::Evidence(a), TxKind::Evidence(b)) => a.first.index == b.first.index && a.offender() == b.offender(),
+        _ => false,
+    }
+}
+
+fn index_block(tx_index: &mut HashMap<Hash256, TxLocation>, block: &Block) {
+    for (position, transaction) in block.transactions.iter().enumerate() {
+        tx_index.insert(transaction.id(), TxLocation {
+            block_hash: block.hash,
+            height: block.index,
+            position,
+        });
+    }
+}
+
+impl Transaction {
+    pub fn new(sender: String, receiver: String, amount: f32) -> Self {
+        Transaction {
+            sender,
+            receiver,
+            amount,
+            nonce: 0,
+            extra_nonce: 0,
+            kind: TxKind::Transfer,
+        }
+    }
+
+    pub fn id(&self) -> Hash256 {
+        Hash256::digest(serde_json::to_string(self).unwrap().as_bytes())
+    }
+    
+    // How much this transaction changes the spendable balance of an address
+    pub fn balance_delta(&self, address: &str) -> f32 {
+        let mut delta = 0.0;
+        if self.sender == address {
+            delta -= self.amount;
+        }
+        if self.receiver == address && self.kind.is_transfer() {
+            delta += self.amount;
+        }
+        delta
+    }
+    
+    // A mining reward, the one transfer a block may make out of nothing
+    pub fn is_coinbase(&self) -> bool {
+        self.sender == COINBASE_SENDER && self.kind.is_transfer()
+    }
+    
+    // Evidence must prove a double sign by the validator it names
+    pub fn is_well_formed(&self) -> bool {
+        match &self.kind {
+            TxKind::Evidence(evidence) => evidence.is_valid() && evidence.offender() == Some(self.receiver.as_str()),
+            _ => true,
+        }
+    }
+}
+
+impl TxKind {
+    pub fn is_transfer(&self) -> bool {
+        *self == TxKind::Transfer
+    }
+}
+
+impl AccountState {
+    pub fn apply(&mut self, transaction: &Transaction) {
+        *self.balances.entry(transaction.sender.clone()).or_insert(0.0) -= transaction.amount;
+        match &transaction.kind {
+            TxKind::Transfer => {
+                *self.balances.entry(transaction.receiver.clone()).or_insert(0.0) += transaction.amount;
+            }
+            TxKind::Stake => {
+                *self.stakes.entry(transaction.receiver.clone()).or_insert(0.0) += transaction.amount;
+            }
+            TxKind::Evidence(_) => {
+                self.stakes.remove(&transaction.receiver);
+                self.slashed.insert(transaction.receiver.clone());
+            }
+        }
+        *self.nonces.entry(transaction.sender.clone()).or_insert(0) += 1;
+    }
+    
+    pub fn balance(&self, address: &str) -> f32 {
+        self.balances.get(address).copied().unwrap_or(0.0)
+    }
+    
+    pub fn nonce(&self, address: &str) -> u64 {
+        self.nonces.get(address).copied().unwrap_or(0)
+    }
 }
 
 impl Block {
-    pub fn new(index: u64, timestamp: u64, transactions: Vec<Transaction>, previous_hash: String) -> Self {
+    pub fn new(index: u64, timestamp: u64, transactions: Vec<Transaction>, previous_hash: Hash256) -> Self {
         let mut block = Block {
             index,
             timestamp,
             transactions,
             previous_hash,
             nonce: 0,
-            hash: String::new(),
+            hash: Hash256::ZERO,
+            version: BLOCK_VERSION,
+            tx_root: Hash256::ZERO,
+            signature: None,
         };
         
+        block.tx_root = block.calculate_tx_root();
+        block.hash = block.calculate_hash();
+        block
+    }
+    
+    // Genesis blocks keep the version 0 encoding, so fixed genesis hashes stay valid
+    pub fn genesis(timestamp: u64) -> Self {
+        let mut block = Block::new(0, timestamp, Vec::new(), Hash256::ZERO);
+        block.version = 0;
+        block.tx_root = Hash256::ZERO;
         block.hash = block.calculate_hash();
         block
     }
     
-    pub fn calculate_hash(&self) -> String {
+    pub fn calculate_tx_root(&self) -> Hash256 {
+        let ids: Vec<Hash256> = self.transactions.iter().map(Transaction::id).collect();
+        merkle_root(&ids)
+    }
+    
+    // Block id. Version 1 blocks hash their header alone, so this works without the body.
+    pub fn calculate_hash(&self) -> Hash256 {
+        if self.version >= 1 {
+            return self.header().calculate_hash();
+        }
+        
+        // Version 0 hashes the parent as its serialized form, "0" for a genesis block
         let block_data = serde_json::json!({
             "index": self.index,
             "timestamp": self.timestamp,
@@ -51,170 +592,1072 @@ impl Block {
             "nonce": self.nonce,
         });
         
-        let mut hasher = Sha256::new();
-        hasher.update(block_data.to_string().as_bytes());
-        format!("{:x}", hasher.finalize())
+        Hash256::digest(block_data.to_string().as_bytes())
     }
     
-    pub fn mine_block(&mut self, difficulty: usize) {
-        let target = "0".repeat(difficulty);
-        
-        while &self.hash[0..difficulty] != target {
-            self.nonce += 1;
-            self.hash = self.calculate_hash();
+    pub fn mine_block(&mut self, difficulty: usize, algorithm: PowAlgorithm, threads: usize, clock: &dyn Clock) {
+        if self.version >= 1 {
+            *self = mining::mine(self, difficulty, algorithm, threads, clock);
+        } else {
+            while !algorithm.meets_difficulty(&self.hash, difficulty) {
+                self.nonce += 1;
+                self.hash = self.calculate_hash();
+            }
         }
         
-        println!("Block mined: {}", self.hash);
+        info!("Block mined: {}", self.hash);
+    }
+    
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            index: self.index,
+            timestamp: self.timestamp,
+            previous_hash: self.previous_hash,
+            nonce: self.nonce,
+            hash: self.hash,
+            version: self.version,
+            tx_root: self.tx_root,
+            signature: self.signature.clone(),
+        }
+    }
+    
+    // A block whose body has been pruned away
+    pub fn from_header(header: BlockHeader) -> Self {
+        Block {
+            index: header.index,
+            timestamp: header.timestamp,
+            transactions: Vec::new(),
+            previous_hash: header.previous_hash,
+            nonce: header.nonce,
+            hash: header.hash,
+            version: header.version,
+            tx_root: header.tx_root,
+            signature: header.signature,
+        }
+    }
+}
+
+impl BlockHeader {
+    // Everything in the version 1 encoding before the nonce
+    pub fn encode_prefix(&self) -> [u8; HEADER_LEN - 8] {
+        let mut prefix = [0u8; HEADER_LEN - 8];
+        prefix[0..4].copy_from_slice(&self.version.to_le_bytes());
+        prefix[4..12].copy_from_slice(&self.index.to_le_bytes());
+        prefix[12..20].copy_from_slice(&self.timestamp.to_le_bytes());
+        prefix[20..52].copy_from_slice(self.previous_hash.as_bytes());
+        prefix[52..84].copy_from_slice(self.tx_root.as_bytes());
+        prefix
+    }
+    
+    pub fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut header = [0u8; HEADER_LEN];
+        header[..HEADER_LEN - 8].copy_from_slice(&self.encode_prefix());
+        header[HEADER_LEN - 8..].copy_from_slice(&self.nonce.to_le_bytes());
+        header
+    }
+    
+    // Id of a version 1 block
+    pub fn calculate_hash(&self) -> Hash256 {
+        Hash256::digest(&self.encode())
+    }
+}
+
+// An unsealed block with everything needed to seal it apart from the chain, so a long
+// proof-of-work search does not hold up other requests
+pub struct BlockTemplate {
+    pub block: Block,
+    // Header of the tip the block builds on and its account state
+    parent: BlockHeader,
+    state: AccountState,
+    engine: Box<dyn Consensus>,
+    signer: Option<Keypair>,
+    threads: usize,
+    clock: Arc<dyn Clock>,
+}
+
+impl BlockTemplate {
+    pub fn seal(mut self) -> Result<Block, ConsensusError> {
+        info!("Sealing block {} ({:?})", self.block.index, self.engine.requirement(&self.block, &self.parent, &self.state));
+        self.engine.seal(&mut self.block, &self.parent, &self.state, &SealContext {
+            signer: self.signer.as_ref(),
+            threads: self.threads,
+            clock: self.clock.as_ref(),
+        })?;
+        Ok(self.block)
     }
 }
 
 impl Blockchain {
-    pub fn new(difficulty: usize, mining_reward: f32) -> Self {
+    pub fn new(network_id: &str, params: ChainParams) -> Self {
+        Self::with_clock(network_id, params, Arc::new(SystemClock))
+    }
+    
+    // A new chain whose genesis and later blocks are timestamped by `clock`
+    pub fn with_clock(network_id: &str, params: ChainParams, clock: Arc<dyn Clock>) -> Self {
+        let mut blockchain = Self::from_blocks(Vec::new(), network_id, params);
+        blockchain.clock = clock;
+        blockchain.create_genesis_block();
+        blockchain
+    }
+    
+    fn from_blocks(chain: Vec<Block>, network_id: &str, params: ChainParams) -> Self {
         let mut blockchain = Blockchain {
-            chain: Vec::new(),
+            chain,
             pending_transactions: Vec::new(),
-            difficulty,
-            mining_reward,
+            network_id: network_id.to_string(),
+            engine: consensus::engine(&params),
+            params,
+            tx_index: HashMap::new(),
+            store: None,
+            pruned: None,
+            prune_depth: None,
+            signer: None,
+            mining_threads: 1,
+            clock: Arc::new(SystemClock),
+            events: EventBus::new(),
         };
         
-        blockchain.create_genesis_block();
+        blockchain.rebuild_tx_index();
         blockchain
     }
     
+    // Open a disk-backed chain from a data directory, creating it with a fresh genesis block if empty.
+    // A directory that was already initialized keeps its own network id and parameters.
+    pub fn open<P: AsRef<Path>>(dir: P, network_id: &str, params: ChainParams) -> Result<Self> {
+        let mut store = BlockStore::open(&dir)?;
+        let state = storage::read_node_state(&dir)?;
+        if let Some(state) = &state {
+            store.set_compacted(state.compacted);
+        }
+        
+        let (network_id, params, mempool, pruned) = match state {
+            Some(mut state) => {
+                // Checkpoints asked for now are enforced on top of the stored ones
+                merge_checkpoints(&mut state.params, &params)?;
+                let mut requested = params;
+                requested.checkpoints = state.params.checkpoints.clone();
+                if state.network_id != network_id || state.params != requested {
+                    warn!(
+                        "{} was initialized for network {} with {:?}, ignoring requested network {} with {:?}",
+                        dir.as_ref().display(), state.network_id, state.params, network_id, requested,
+                    );
+                }
+                (state.network_id, state.params, state.mempool, state.pruned)
+            }
+            None => (network_id.to_string(), params, Vec::new(), None),
+        };
+        
+        let mut blockchain = if store.is_empty() {
+            Self::new(&network_id, params)
+        } else {
+            let pruned_height = pruned.as_ref().map(|pruned| pruned.height).unwrap_or(0);
+            let mut blockchain = Self::from_blocks(store.load_all(pruned_height)?, &network_id, params);
+            if let Some(pruned) = pruned {
+                blockchain.set_pruned(pruned);
+            }
+            check_checkpoints(&blockchain.params, &blockchain.chain)?;
+            blockchain.validate_chain().map_err(|source| StorageError::InvalidChain {
+                path: store.dir().display().to_string(),
+                source,
+            })?;
+            blockchain
+        };
+        
+        blockchain.pending_transactions = mempool;
+        blockchain.attach_store(store)?;
+        Ok(blockchain)
+    }
+    
+    // Persist every connected block and the node state next to `store` from now on, first
+    // rewriting any part of it that diverges from the in-memory chain
+    pub fn attach_store(&mut self, store: BlockStore) -> Result<()> {
+        self.store = Some(store);
+        self.sync_store()
+    }
+    
+    // Bring the attached block store and node state in line with the in-memory chain
+    fn sync_store(&mut self) -> Result<()> {
+        let store = match self.store.as_mut() {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+        
+        let common = self.chain
+            .iter()
+            .take_while(|block| store.hash_at(block.index) == Some(block.hash))
+            .count();
+        store.truncate(common as u64)?;
+        for block in &self.chain[common..] {
+            store.append(block)?;
+        }
+        
+        self.persist_state()
+    }
+    
+    // Write the network, parameters and mempool next to the block store, if there is one
+    fn persist_state(&self) -> Result<()> {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+        
+        storage::write_node_state(store.dir(), &NodeState {
+            network_id: self.network_id.clone(),
+            params: self.params.clone(),
+            mempool: self.pending_transactions.clone(),
+            pruned: self.pruned.clone(),
+            compacted: store.compacted(),
+        })?;
+        Ok(())
+    }
+    
+    // Sign sealed blocks with this key, for signature-based consensus engines
+    pub fn set_signer(&mut self, keypair: Keypair) {
+        self.signer = Some(keypair);
+    }
+    
+    // Timestamp blocks with `clock` from now on
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+    
+    // Bus that block, mempool and (through the network layer) peer events are emitted on
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+    
+    // Swap in another chain, carrying over this one's block store, prune depth, signer key,
+    // mining threads, clock and event bus. If the new chain cannot be written to the store,
+    // this chain stays in place with its store and signer.
+    pub fn replace_with(&mut self, mut other: Blockchain) -> Result<()> {
+        other.prune_depth = self.prune_depth;
+        other.mining_threads = self.mining_threads;
+        other.clock = self.clock.clone();
+        other.events = self.events.clone();
+        other.signer = self.signer.take();
+        other.store = self.store.take();
+        if let Err(e) = other.sync_store() {
+            self.signer = other.signer.take();
+            self.store = other.store.take();
+            // Put back on disk whatever of this chain the failed write replaced
+            self.sync_store()?;
+            return Err(e);
+        }
+        
+        let old = std::mem::replace(self, other);
+        self.announce_replacement(&old);
+        self.compact_store(COMPACT_INTERVAL);
+        Ok(())
+    }
+    
+    // Announce the switch from `old` to this chain, and the pending transactions it dropped
+    fn announce_replacement(&self, old: &Blockchain) {
+        let common = old.chain
+            .iter()
+            .zip(&self.chain)
+            .take_while(|(ours, theirs)| ours.hash == theirs.hash)
+            .count();
+        
+        if common < old.chain.len() || common < self.chain.len() {
+            self.events.emit(Event::ChainReplaced {
+                common: common as u64,
+                disconnected: old.chain[common..].to_vec(),
+                connected: self.chain[common..].to_vec(),
+                tip: self.get_latest_block().header(),
+            });
+        }
+        
+        for transaction in &old.pending_transactions {
+            let id = transaction.id();
+            let kept = self.tx_index.contains_key(&id)
+                || self.pending_transactions.iter().any(|pending| pending.id() == id);
+            if !kept {
+                self.events.emit(Event::TxEvicted { id });
+            }
+        }
+    }
+    
     pub fn create_genesis_block(&mut self) {
-        let genesis_block = Block::new(
-            0,
-            Self::get_timestamp(),
-            Vec::new(),
-            String::from("0"),
-        );
+        let genesis_block = Block::genesis(self.params.genesis_timestamp.unwrap_or_else(|| self.clock.now()));
         
         self.chain.push(genesis_block);
     }
     
+    // Every way of building a chain either creates a genesis block or validates the chain,
+    // which rejects an empty one, so there is always a tip
     pub fn get_latest_block(&self) -> &Block {
-        self.chain.last().unwrap()
+        self.chain.last().expect("Chain always holds a genesis block")
     }
     
-    pub fn add_transaction(&mut self, sender: String, receiver: String, amount: f32) {
-        let transaction = Transaction {
-            sender,
-            receiver,
-            amount,
+    pub fn add_transaction(&mut self, sender: String, receiver: String, amount: f32) -> Result<Hash256> {
+        self.submit_transaction(Transaction::new(sender, receiver, amount))
+    }
+    
+    // Stake funds of `owner` on a validator, raising its chance to propose blocks under proof of stake
+    pub fn add_stake(&mut self, owner: String, validator: String, amount: f32) -> Result<Hash256> {
+        let mut transaction = Transaction::new(owner, validator, amount);
+        transaction.kind = TxKind::Stake;
+        self.submit_transaction(transaction)
+    }
+    
+    // Queue double-sign evidence, which slashes the offending validator's stake once mined
+    pub fn submit_evidence(&mut self, reporter: String, evidence: DoubleSignEvidence) -> Result<Hash256> {
+        let offender = match evidence.offender() {
+            Some(offender) if evidence.is_valid() => offender.to_string(),
+            _ => return Err(ValidationError::NotDoubleSign.into()),
         };
         
-        self.pending_transactions.push(transaction);
+        let mut transaction = Transaction::new(reporter, offender, 0.0);
+        transaction.kind = TxKind::Evidence(Box::new(evidence));
+        self.submit_transaction(transaction)
+    }
+    
+    fn submit_transaction(&mut self, mut transaction: Transaction) -> Result<Hash256> {
+        transaction.nonce = self.next_nonce(&transaction.sender);
+        self.admit_transaction(transaction)
     }
     
-    pub fn mine_pending_transactions(&mut self, mining_reward_address: &str) {
-        // Add mining reward transaction
-        self.add_transaction(
-            String::from("BLOCKCHAIN"),
-            mining_reward_address.to_string().clone(),
-            self.mining_reward,
+    // Take a transaction relayed by a peer, keeping the nonce its sender gave it. Returns
+    // whether it was new; one already pending or confirmed is left alone.
+    pub fn receive_transaction(&mut self, transaction: Transaction) -> Result<bool> {
+        let id = transaction.id();
+        if self.tx_index.contains_key(&id) || self.pending_transactions.iter().any(|pending| pending.id() == id) {
+            return Ok(false);
+        }
+        if !transaction.is_well_formed() {
+            return Err(ValidationError::NotDoubleSign.into());
+        }
+        
+        self.admit_transaction(transaction)?;
+        Ok(true)
+    }
+    
+    // Check a transaction against the mempool and queue it
+    fn admit_transaction(&mut self, transaction: Transaction) -> Result<Hash256> {
+        check_transaction(&transaction, &self.mempool_state())?;
+        
+        let id = transaction.id();
+        self.pending_transactions.push(transaction.clone());
+        
+        if let Err(e) = self.persist_state() {
+            self.pending_transactions.pop();
+            return Err(e);
+        }
+        
+        self.events.emit(Event::TxAccepted { id, transaction });
+        Ok(id)
+    }
+    
+    // Account state once every pending transaction is applied on top of the tip, which is
+    // what a new transaction is checked against
+    fn mempool_state(&self) -> AccountState {
+        let mut state = self.state_at(self.get_latest_block().index).unwrap_or_default();
+        for transaction in &self.pending_transactions {
+            state.apply(transaction);
+        }
+        state
+    }
+    
+    // Next sequence number for a sender, counting both confirmed and pending transactions
+    fn next_nonce(&self, address: &str) -> u64 {
+        let pruned_sent = self.pruned.as_ref().map(|pruned| pruned.state.nonce(address)).unwrap_or(0);
+        let sent = self.chain
+            .iter()
+            .flat_map(|block| block.transactions.iter())
+            .chain(self.pending_transactions.iter())
+            .filter(|transaction| transaction.sender == address)
+            .count();
+        
+        pruned_sent + sent as u64 + 1
+    }
+    
+    pub fn mine_pending_transactions(&mut self, mining_reward_address: &str) -> Result<()> {
+        let block = self.block_template(mining_reward_address).seal()?;
+        self.accept_block(block)
+    }
+    
+    // Assemble the pending transactions and a mining reward into an unsealed block on the tip
+    pub fn block_template(&self, mining_reward_address: &str) -> BlockTemplate {
+        let latest_block = self.get_latest_block();
+        
+        // Add mining reward transaction, using the new height as nonce so every reward has a unique id
+        let mut reward = Transaction::new(
+            COINBASE_SENDER.to_string(),
+            mining_reward_address.to_string(),
+            self.params.mining_reward,
         );
+        reward.nonce = latest_block.index + 1;
         
-        let block = {
-            let latest_block = self.get_latest_block();
-            let mut new_block = Block::new(
-                latest_block.index + 1,
-                Self::get_timestamp(),
-                self.pending_transactions.clone(),
-                latest_block.hash.clone(),
-            );
-            
-            new_block.mine_block(self.difficulty);
-            new_block
-        };
+        // Pending transactions the chain has since made invalid are left for later
+        let state = self.state_at(latest_block.index).unwrap_or_default();
+        let mut block_state = state.clone();
+        let mut transactions = Vec::new();
+        for transaction in &self.pending_transactions {
+            // Evidence the node reported itself is numbered as it goes into a block, since
+            // every mining reward also advances the nonce of the reward sender
+            let mut transaction = transaction.clone();
+            if transaction.sender == COINBASE_SENDER {
+                transaction.nonce = block_state.nonce(COINBASE_SENDER) + 1;
+            }
+            match check_transaction(&transaction, &block_state) {
+                Ok(()) => {
+                    block_state.apply(&transaction);
+                    transactions.push(transaction);
+                }
+                Err(e) => warn!("Leaving pending transaction {} out of block: {}", transaction.id(), e),
+            }
+        }
+        transactions.push(reward);
+        
+        BlockTemplate {
+            block: Block::new(latest_block.index + 1, self.clock.now(), transactions, latest_block.hash),
+            parent: latest_block.header(),
+            state,
+            engine: consensus::engine(&self.params),
+            signer: self.signer.clone(),
+            threads: self.mining_threads,
+            clock: self.clock.clone(),
+        }
+    }
+    
+    // Validate a sealed block and connect it if it still extends the tip, dropping the
+    // transactions it confirms from the mempool. Evidence is dropped once any evidence of
+    // the same double sign is confirmed, whatever its id was renumbered to.
+    pub fn accept_block(&mut self, block: Block) -> Result<()> {
+        self.validate_next_block(&block)?;
+        self.connect_block(block)?;
+        
+        let tx_index = &self.tx_index;
+        let tip = self.chain.last().expect("A block was just connected");
+        self.pending_transactions.retain(|transaction| {
+            !tx_index.contains_key(&transaction.id())
+                && !tip.transactions.iter().any(|confirmed| same_offence(confirmed, transaction))
+        });
+        self.persist_state()?;
+        self.compact_store(COMPACT_INTERVAL);
+        Ok(())
+    }
+    
+    // Take a block announced by a peer: connect it if it extends the tip. A block that
+    // extends the tip but fails validation is an error. A heavier fork is only reached by
+    // fetching the peer's chain, which happens when its blocks arrive as orphans.
+    pub fn submit_block(&mut self, block: Block) -> Result<BlockOutcome> {
+        if self.get_block_by_hash(&block.hash).is_some() {
+            return Ok(BlockOutcome::Known);
+        }
+        if block.previous_hash == self.get_latest_block().hash {
+            self.accept_block(block)?;
+            return Ok(BlockOutcome::Connected);
+        }
         
+        if self.get_block_by_hash(&block.previous_hash).is_some() {
+            Ok(BlockOutcome::Stale)
+        } else {
+            Ok(BlockOutcome::Orphan)
+        }
+    }
+    
+    // Append a block to the tip, writing it to the block store first so memory never runs ahead of disk
+    fn connect_block(&mut self, block: Block) -> Result<()> {
+        if let Some(store) = self.store.as_mut() {
+            store.append(&block)?;
+        }
+        
+        index_block(&mut self.tx_index, &block);
+        self.events.emit(Event::BlockConnected(block.clone()));
         self.chain.push(block);
-        self.pending_transactions = Vec::new();
+        
+        if let Some(depth) = self.prune_depth {
+            self.prune_bodies(depth);
+        }
+        Ok(())
+    }
+    
+    pub fn pruned_height(&self) -> u64 {
+        self.pruned.as_ref().map(|pruned| pruned.height).unwrap_or(0)
+    }
+    
+    // Keep bodies only for the last `depth` blocks from now on, pruning older ones right away.
+    // The block store is compacted in batches, as when blocks are connected, so restarting
+    // a pruned node does not rewrite its block log. Returns how many blocks were newly pruned.
+    pub fn set_prune_depth(&mut self, depth: u64) -> Result<u64> {
+        self.prune_depth = Some(depth);
+        let pruned = self.prune_bodies(depth);
+        if pruned > 0 {
+            self.persist_state()?;
+        }
+        self.compact_store(COMPACT_INTERVAL);
+        Ok(pruned)
+    }
+    
+    // Drop the bodies of all blocks more than `depth` below the tip, keeping their headers
+    // for validation and folding their transactions into the pruned account state, and
+    // compact the block store so the bodies no longer take up disk space.
+    // Returns how many blocks were newly pruned.
+    pub fn prune(&mut self, depth: u64) -> Result<u64> {
+        let pruned = self.prune_bodies(depth);
+        if pruned > 0 {
+            self.persist_state()?;
+        }
+        let pruned_height = self.pruned_height();
+        if let Some(store) = self.store.as_mut() {
+            if pruned_height > store.compacted() {
+                store.compact(pruned_height)?;
+                self.persist_state()?;
+            }
+        }
+        Ok(pruned)
+    }
+    
+    // Free the disk space of pruned bodies once at least `batch` more blocks have been pruned
+    // than the block store was last compacted to. This only runs after the pruned state is
+    // persisted, so the state on disk never expects bodies the store has dropped, and the new
+    // compacted height is persisted afterwards so the next process starts from it. A failure
+    // leaves the bodies in place and is retried on the next batch.
+    fn compact_store(&mut self, batch: u64) {
+        let pruned_height = self.pruned_height();
+        let compacted = match self.store.as_mut() {
+            Some(store) if pruned_height >= store.compacted() + batch.max(1) => store.compact(pruned_height),
+            _ => return,
+        };
+        if let Err(e) = compacted.map_err(Into::into).and_then(|_| self.persist_state()) {
+            warn!("Could not compact the block store: {}", e);
+        }
+    }
+    
+    fn prune_bodies(&mut self, depth: u64) -> u64 {
+        let from = self.pruned_height();
+        let to = (self.get_latest_block().index + 1).saturating_sub(depth.max(1));
+        if to <= from {
+            return 0;
+        }
+        
+        let mut pruned = self.pruned.take().unwrap_or(PrunedState {
+            height: 0,
+            state: AccountState::default(),
+        });
+        for block in &mut self.chain[from as usize..to as usize] {
+            for transaction in block.transactions.drain(..) {
+                self.tx_index.remove(&transaction.id());
+                pruned.state.apply(&transaction);
+            }
+        }
+        
+        pruned.height = to;
+        self.pruned = Some(pruned);
+        to - from
+    }
+    
+    // Adopt a pruned state, dropping any bodies it already accounts for
+    fn set_pruned(&mut self, pruned: PrunedState) {
+        for block in self.chain.iter_mut().take(pruned.height as usize) {
+            block.transactions.clear();
+        }
+        
+        self.pruned = Some(pruned);
+        self.rebuild_tx_index();
+    }
+    
+    // Account state once the block at `height` is applied, if its history has not been pruned away
+    pub fn state_at(&self, height: u64) -> Option<AccountState> {
+        let from = self.pruned_height();
+        if height + 1 < from || height >= self.chain.len() as u64 {
+            return None;
+        }
+        
+        let mut state = self.pruned.as_ref().map(|pruned| pruned.state.clone()).unwrap_or_default();
+        for block in &self.chain[from as usize..=height as usize] {
+            for transaction in &block.transactions {
+                state.apply(transaction);
+            }
+        }
+        Some(state)
+    }
+    
+    pub fn export_snapshot(&self, height: u64) -> Option<Snapshot> {
+        let state = self.state_at(height)?;
+        let block = &self.chain[height as usize];
+        
+        Some(Snapshot {
+            network_id: self.network_id.clone(),
+            params: self.params.clone(),
+            height,
+            block_hash: block.hash,
+            state,
+            headers: self.chain[..=height as usize].iter().map(Block::header).collect(),
+        })
+    }
+    
+    // Start a chain from a snapshot whose block hash is known to be good. Headers are checked
+    // for linkage and proof of work; the account state is trusted as of that block.
+    pub fn from_snapshot(snapshot: Snapshot, trusted_hash: &Hash256) -> Result<Self> {
+        if snapshot.block_hash != *trusted_hash {
+            return Err(ValidationError::UntrustedSnapshot {
+                expected: *trusted_hash,
+                found: snapshot.block_hash,
+            }
+            .into());
+        }
+        match snapshot.headers.last() {
+            Some(header) if header.index == snapshot.height && header.hash == snapshot.block_hash => {}
+            _ => return Err(ValidationError::SnapshotHeadersMismatch.into()),
+        }
+        
+        let chain = snapshot.headers.into_iter().map(Block::from_header).collect();
+        let mut blockchain = Self::from_blocks(chain, &snapshot.network_id, snapshot.params);
+        blockchain.set_pruned(PrunedState {
+            height: snapshot.height + 1,
+            state: snapshot.state,
+        });
+        
+        blockchain.validate_chain()?;
+        
+        Ok(blockchain)
+    }
+    
+    fn rebuild_tx_index(&mut self) {
+        self.tx_index.clear();
+        for block in &self.chain {
+            index_block(&mut self.tx_index, block);
+        }
     }
     
-    pub fn is_chain_valid(&self) -> bool {
-        for i in 1..self.chain.len() {
-            let current_block = &self.chain[i];
-            let previous_block = &self.chain[i - 1];
+    pub fn get_transaction(&self, id: &Hash256) -> Option<TransactionStatus> {
+        if let Some(location) = self.tx_index.get(id) {
+            let block = &self.chain[location.height as usize];
+            return Some(TransactionStatus::Confirmed {
+                transaction: block.transactions[location.position].clone(),
+                location: location.clone(),
+                confirmations: self.get_latest_block().index - location.height + 1,
+            });
+        }
+        
+        self.pending_transactions
+            .iter()
+            .find(|transaction| transaction.id() == *id)
+            .map(|transaction| TransactionStatus::Pending(transaction.clone()))
+    }
+    
+    pub fn get_block(&self, height: u64) -> Option<&Block> {
+        self.chain.get(height as usize)
+    }
+    
+    pub fn get_block_by_hash(&self, hash: &Hash256) -> Option<&Block> {
+        self.chain.iter().rev().find(|block| block.hash == *hash)
+    }
+    
+    // Up to `limit` consecutive blocks starting at height `from`
+    pub fn get_blocks(&self, from: u64, limit: usize) -> &[Block] {
+        let start = (from as usize).min(self.chain.len());
+        let end = start.saturating_add(limit).min(self.chain.len());
+        &self.chain[start..end]
+    }
+    
+    pub fn get_tip(&self) -> ChainTip {
+        let tip = self.get_latest_block();
+        ChainTip {
+            height: tip.index,
+            hash: tip.hash,
+            timestamp: tip.timestamp,
+            weight: self.chain_weight(&self.chain),
+            pruned_height: self.pruned_height(),
+        }
+    }
+    
+    pub fn get_pending_transactions(&self) -> &[Transaction] {
+        &self.pending_transactions
+    }
+    
+    pub fn get_stats(&self) -> ChainStats {
+        let tip = self.get_latest_block();
+        let average_block_time = (tip.index > 0)
+            .then(|| tip.timestamp.saturating_sub(self.chain[0].timestamp) as f64 / tip.index as f64);
+        
+        let with_bodies = &self.chain[self.pruned_height().max(1) as usize..];
+        let transactions: u64 = with_bodies.iter().map(|block| block.transactions.len() as u64).sum();
+        let transactions_per_block = (!with_bodies.is_empty()).then(|| transactions as f64 / with_bodies.len() as f64);
+        
+        let difficulty = match self.params.consensus {
+            ConsensusConfig::ProofOfWork => Some(self.params.difficulty),
+            _ => None,
+        };
+        
+        ChainStats {
+            blocks: self.chain.len() as u64,
+            average_block_time,
+            transactions,
+            transactions_per_block,
+            pending_transactions: self.pending_transactions.len(),
+            difficulty,
+        }
+    }
+    
+    // Structural checks of the chain as stored; its transactions were checked against the
+    // account state when each block was connected
+    pub fn validate_chain(&self) -> Result<(), ValidationError> {
+        self.validate_blocks(&self.chain, self.pruned_height(), 0, u64::MAX)
+    }
+    
+    // Validate a run of blocks starting at genesis against this chain's parameters.
+    // Hashes are only recomputed from `pruned_height` up, since lower blocks have no bodies,
+    // and seals are only checked from `assume_valid_height` up. Account state is replayed
+    // alongside so engines can check seals against the stakes each block builds on, and
+    // blocks from `new_from` up, which this node has not accepted before, also have their
+    // transactions checked against it.
+    fn validate_blocks(
+        &self,
+        blocks: &[Block],
+        pruned_height: u64,
+        assume_valid_height: u64,
+        new_from: u64,
+    ) -> Result<(), ValidationError> {
+        let genesis_block = blocks.first().ok_or(ValidationError::NoGenesis)?;
+        
+        if genesis_block.index != 0 || genesis_block.hash != genesis_block.calculate_hash() {
+            return Err(ValidationError::InvalidGenesis);
+        }
+        
+        check_checkpoints(&self.params, blocks)?;
+        
+        let mut state = if pruned_height == 0 {
+            let mut state = AccountState::default();
+            for transaction in &genesis_block.transactions {
+                state.apply(transaction);
+            }
+            Some(state)
+        } else {
+            None
+        };
+        
+        for i in 1..blocks.len() {
+            let current_block = &blocks[i];
+            let previous_block = &blocks[i - 1];
             
-            // Verify current hash
-            if current_block.hash != current_block.calculate_hash() {
-                println!("Current hash is invalid");
-                return false;
+            // Account state below the pruned range is only known as a whole
+            if state.is_none() && current_block.index == pruned_height {
+                state = self.pruned.as_ref().map(|pruned| pruned.state.clone());
+            }
+            
+            // Version 0 hashes need the body, so are only checked above the pruned range
+            let has_body = current_block.index >= pruned_height;
+            let check_seal = current_block.index >= assume_valid_height;
+            self.check_block(current_block, previous_block, state.as_ref(), has_body, check_seal)?;
+            if current_block.index >= new_from {
+                self.check_timestamp(current_block)?;
+                if let Some(state) = state.as_ref() {
+                    check_transactions(current_block, state, self.params.mining_reward)?;
+                }
             }
             
-            // Verify link to previous hash
-            if current_block.previous_hash != previous_block.hash {
-                println!("Link to previous hash is broken");
-                return false;
+            if let Some(state) = state.as_mut() {
+                for transaction in &current_block.transactions {
+                    state.apply(transaction);
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    // Checks on a single block against its parent. `state` is the account state the block
+    // builds on, when known.
+    fn check_block(
+        &self,
+        block: &Block,
+        parent: &Block,
+        state: Option<&AccountState>,
+        has_body: bool,
+        check_seal: bool,
+    ) -> Result<(), ValidationError> {
+        check_header(self.engine.as_ref(), block, parent, state, has_body, check_seal)?;
+        
+        // Verify the header commits to the transactions
+        if has_body && block.version >= 1 && block.tx_root != block.calculate_tx_root() {
+            return Err(ValidationError::InvalidTxRoot { hash: block.hash });
+        }
+        
+        // The Merkle tree pairs an odd node with itself, so repeating the last transactions
+        // would give a different body under the same root
+        let mut txids = HashSet::new();
+        for transaction in &block.transactions {
+            let txid = transaction.id();
+            if !txids.insert(txid) {
+                return Err(ValidationError::DuplicateTransaction { hash: block.hash, txid });
             }
         }
         
-        true
+        if !block.transactions.iter().all(Transaction::is_well_formed) {
+            return Err(ValidationError::InvalidEvidence { hash: block.hash });
+        }
+        
+        Ok(())
+    }
+    
+    // New blocks may not be timestamped further ahead of the local clock than the engine allows
+    fn check_timestamp(&self, block: &Block) -> Result<(), ValidationError> {
+        let limit = self.clock.now().saturating_add(self.engine.max_future_drift());
+        if block.timestamp > limit {
+            return Err(ValidationError::TimestampInFuture {
+                hash: block.hash,
+                timestamp: block.timestamp,
+            });
+        }
+        Ok(())
+    }
+    
+    // Fully validate a block that is meant to extend the current tip
+    pub fn validate_next_block(&self, block: &Block) -> Result<(), ValidationError> {
+        let tip = self.get_latest_block();
+        if block.previous_hash != tip.hash {
+            return Err(ValidationError::NotOnTip { hash: block.hash });
+        }
+        if let Some(checkpoint) = self.params.checkpoints.get(&block.index) {
+            if *checkpoint != block.hash {
+                return Err(ValidationError::CheckpointConflict {
+                    height: block.index,
+                    expected: *checkpoint,
+                    found: block.hash,
+                });
+            }
+        }
+        
+        self.check_timestamp(block)?;
+        let state = self.state_at(tip.index).unwrap_or_default();
+        self.check_block(block, tip, Some(&state), true, true)?;
+        check_transactions(block, &state, self.params.mining_reward)
+    }
+    
+    // Total fork choice weight of a run of blocks under this chain's consensus engine
+    pub fn chain_weight(&self, blocks: &[Block]) -> u128 {
+        blocks.iter().skip(1).map(|block| self.engine.block_weight(block)).sum()
+    }
+    
+    // Fork choice for a full chain received from a peer: adopt it if it shares our genesis,
+    // respects every checkpoint and is heavier than ours. Seals up to the last checkpoint
+    // are not rechecked, since the checkpoint hash already commits to everything beneath it.
+    // Returns whether the chain was adopted.
+    pub fn try_replace_chain(&mut self, candidate: Vec<Block>) -> Result<bool> {
+        self.report_double_signs(&candidate)?;
+        
+        if self.chain_weight(&candidate) <= self.chain_weight(&self.chain) {
+            return Ok(false);
+        }
+        
+        if candidate.first().map(|block| &block.hash) != self.chain.first().map(|block| &block.hash) {
+            warn!("Rejecting chain with a different genesis block");
+            return Ok(false);
+        }
+        
+        let assume_valid_height = match self.params.last_checkpoint() {
+            Some(height) if (height as usize) < candidate.len() => height + 1,
+            _ => 0,
+        };
+        // Blocks we already hold were checked when connected, and checkpointed ones are trusted
+        let common = candidate
+            .iter()
+            .zip(&self.chain)
+            .take_while(|(theirs, ours)| theirs.hash == ours.hash)
+            .count() as u64;
+        if let Err(e) = self.validate_blocks(&candidate, 0, assume_valid_height, common.max(assume_valid_height)) {
+            warn!("Rejecting invalid chain of {} blocks: {}", candidate.len(), e);
+            return Ok(false);
+        }
+        
+        // Keep pending transactions the new chain has not confirmed
+        let mut replacement = Self::from_blocks(candidate, &self.network_id, self.params.clone());
+        replacement.pending_transactions = self.pending_transactions
+            .iter()
+            .filter(|transaction| !replacement.tx_index.contains_key(&transaction.id()))
+            .cloned()
+            .collect();
+        if let Some(depth) = self.prune_depth {
+            replacement.prune_bodies(depth);
+        }
+        
+        self.replace_with(replacement)?;
+        Ok(true)
+    }
+    
+    // Under proof of stake, queue evidence against any validator that signed a block of
+    // `candidate` and a different block at the same height of our chain
+    fn report_double_signs(&mut self, candidate: &[Block]) -> Result<()> {
+        if !matches!(self.params.consensus, ConsensusConfig::ProofOfStake { .. }) {
+            return Ok(());
+        }
+        
+        let found: Vec<DoubleSignEvidence> = candidate
+            .iter()
+            .zip(&self.chain)
+            .filter_map(|(theirs, ours)| DoubleSignEvidence::from_headers(ours.header(), theirs.header()))
+            .collect();
+        
+        for evidence in found {
+            let reported = self.chain
+                .iter()
+                .flat_map(|block| block.transactions.iter())
+                .chain(self.pending_transactions.iter())
+                .any(|transaction| match &transaction.kind {
+                    TxKind::Evidence(known) => {
+                        known.first.index == evidence.first.index && known.offender() == evidence.offender()
+                    }
+                    _ => false,
+                });
+            if !reported {
+                warn!("Validator {:?} double-signed at height {}", evidence.offender(), evidence.first.index);
+                self.submit_evidence(COINBASE_SENDER.to_string(), evidence)?;
+            }
+        }
+        Ok(())
     }
     
     pub fn get_balance(&self, address: &str) -> f32 {
-        let mut balance = 0.0;
+        let mut balance = self.pruned.as_ref().map(|pruned| pruned.state.balance(address)).unwrap_or(0.0);
         
         for block in &self.chain {
             for transaction in &block.transactions {
-                if transaction.sender == address {
-                    balance -= transaction.amount;
-                }
-                
-                if transaction.receiver == address {
-                    balance += transaction.amount;
-                }
+                balance += transaction.balance_delta(address);
             }
         }
         
         balance
     }
     
-    pub fn get_timestamp() -> u64 {
-        SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs()
-    }
-
-    pub fn save_to_disk(&self, path: &str) -> std::io::Result<()> {
-        let serialized = serde_json::to_string(&self.chain)?;
-        std::fs::write(path, serialized)?;
-        Ok(())
+    // Stake currently locked on a validator
+    pub fn get_stake(&self, validator: &str) -> f32 {
+        self.state_at(self.get_latest_block().index)
+            .and_then(|state| state.stakes.get(validator).copied())
+            .unwrap_or(0.0)
     }
     
-    pub fn load_from_disk(path: &str, difficulty: usize, mining_reward: f32) -> std::io::Result<Self> {
-        let data = std::fs::read_to_string(path)?;
-        let chain: Vec<Block> = serde_json::from_str(&data)?;
+    // Confirmed transactions touching an address, oldest first, paginated by offset and limit.
+    // Pruned blocks are not listed, but their effect is included in the running balance.
+    pub fn get_history(&self, address: &str, offset: usize, limit: usize) -> AddressHistory {
+        let mut balance = self.pruned.as_ref().map(|pruned| pruned.state.balance(address)).unwrap_or(0.0);
+        let mut entries = Vec::new();
         
-        Ok(Blockchain {
-            chain,
-            pending_transactions: Vec::new(),
-            difficulty,
-            mining_reward,
-        })
+        for block in &self.chain {
+            for transaction in &block.transactions {
+                let sent = transaction.sender == address;
+                let direction = match (&transaction.kind, sent, transaction.receiver == address) {
+                    (_, false, false) => continue,
+                    (TxKind::Transfer, true, true) => Direction::SelfTransfer,
+                    (TxKind::Transfer, true, false) => Direction::Sent,
+                    (TxKind::Transfer, false, true) => Direction::Received,
+                    (TxKind::Stake, true, _) => Direction::Staked,
+                    (TxKind::Stake, false, true) => Direction::StakeReceived,
+                    (TxKind::Evidence(_), true, _) => Direction::Reported,
+                    (TxKind::Evidence(_), false, true) => Direction::Slashed,
+                };
+                let counterparty = if sent { &transaction.receiver } else { &transaction.sender };
+                
+                balance += transaction.balance_delta(address);
+                
+                entries.push(HistoryEntry {
+                    txid: transaction.id(),
+                    height: block.index,
+                    timestamp: block.timestamp,
+                    direction,
+                    counterparty: counterparty.clone(),
+                    amount: transaction.amount,
+                    balance,
+                });
+            }
+        }
+        
+        AddressHistory {
+            total: entries.len(),
+            entries: entries.into_iter().skip(offset).take(limit).collect(),
+        }
+    }
+    
+    // Up to `limit` headers starting at `from`, for light clients syncing the header chain
+    pub fn headers_from(&self, from: u64, limit: usize) -> Vec<BlockHeader> {
+        self.chain.iter().skip(from as usize).take(limit).map(Block::header).collect()
     }
-}
-
-// fn main() {
-//     // Create a new blockchain with difficulty 4 and 100 coins as mining reward
-//     let mut my_blockchain = Blockchain::new(4, 100.0);
     
-//     println!("Mining first block...");
-//     my_blockchain.add_transaction(String::from("Alice"), String::from("Bob"), 50.0);
-//     my_blockchain.mine_pending_transactions(String::from("Miner1"));
+    // Merkle proofs of the confirmed transactions touching an address, for light clients.
+    // Pruned blocks and version 0 blocks, whose headers commit to no transactions root,
+    // cannot be proven and are left out.
+    pub fn prove_transactions(&self, address: &str) -> Vec<TxProof> {
+        let mut proofs = Vec::new();
+        
+        for block in self.chain.iter().skip(self.pruned_height() as usize) {
+            if block.version == 0 {
+                continue;
+            }
+            
+            let ids: Vec<Hash256> = block.transactions.iter().map(Transaction::id).collect();
+            for (position, transaction) in block.transactions.iter().enumerate() {
+                if transaction.sender == address || transaction.receiver == address {
+                    proofs.push(TxProof {
+                        transaction: transaction.clone(),
+                        block_hash: block.hash,
+                        height: block.index,
+                        proof: merkle_proof(&ids, position).expect("Position is within the block"),
+                    });
+                }
+            }
+        }
+        
+        proofs
+    }
     
-//     println!("Mining second block...");
-//     my_blockchain.add_transaction(String::from("Bob"), String::from("Charlie"), 25.0);
-//     my_blockchain.add_transaction(String::from("Alice"), String::from("Charlie"), 35.0);
-//     my_blockchain.mine_pending_transactions(String::from("Miner1"));
+    // Save the chain with its network id and parameters, optionally along with the mempool
+    pub fn save_to_disk(&self, path: &str, include_mempool: bool, format: ChainFileFormat) -> Result<()> {
+        let contents = ChainFileContents {
+            network_id: self.network_id.clone(),
+            params: self.params.clone(),
+            chain: self.chain.clone(),
+            mempool: if include_mempool { Some(self.pending_transactions.clone()) } else { None },
+            pruned: self.pruned.clone(),
+        };
+        storage::write_chain_file(path, contents, format)?;
+        Ok(())
+    }
     
-//     println!("Miner1's balance: {}", my_blockchain.get_balance("Miner1"));
+    // Load a saved chain, refusing files that fail their checksum or hold an invalid chain.
+    // The network id and parameters come from the file; the ones given here only apply to
+    // older files that did not record them, except that their checkpoints are always enforced.
+    pub fn load_from_disk(path: &str, network_id: &str, params: &ChainParams) -> Result<Self> {
+        let mut contents = storage::read_chain_file(path, network_id, params)?;
+        merge_checkpoints(&mut contents.params, params)?;
+        
+        let mut blockchain = Self::from_blocks(contents.chain, &contents.network_id, contents.params);
+        blockchain.pending_transactions = contents.mempool.unwrap_or_default();
+        if let Some(pruned) = contents.pruned {
+            blockchain.set_pruned(pruned);
+        }
+        check_checkpoints(&blockchain.params, &blockchain.chain)?;
+        
+        blockchain.validate_chain().map_err(|source| StorageError::InvalidChain {
+            path: path.to_string(),
+            source,
+        })?;
+        
+        Ok(blockchain)
+    }
     
-//     println!("Mining one more block for the miner to get the reward...");
-//     my_blockchain.mine_pending_transactions(String::from("Miner1"));
+    // Read a saved chain back and check it matches this one block for block
+    pub fn verify_saved(&self, path: &str) -> Result<()> {
+        let saved = Self::load_from_disk(path, &self.network_id, &self.params)?;
+        
+        let matches = saved.chain.len() == self.chain.len()
+            && saved.chain.iter().zip(&self.chain).all(|(a, b)| a.hash == b.hash);
+        if !matches {
+            return Err(StorageError::SavedChainMismatch { path: path.to_string() }.into());
+        }
+        
+        Ok(())
+    }
     
-//     println!("Miner1's balance: {}", my_blockchain.get_balance("Miner1"));
+    pub fn save_snapshot(&self, path: &str, height: u64) -> Result<Snapshot> {
+        let snapshot = self.export_snapshot(height).ok_or(StorageError::StateUnavailable(height))?;
+        
+        storage::write_snapshot_file(path, &snapshot)?;
+        Ok(snapshot)
+    }
     
-//     println!("Is blockchain valid? {}", my_blockchain.is_chain_valid());
-// }
\ No newline at end of file
+    pub fn load_snapshot(path: &str, trusted_hash: &Hash256) -> Result<Self> {
+        Self::from_snapshot(storage::read_snapshot_file(path)?, trusted_hash)
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use libp2p::identity::ed25519::{Keypair, PublicKey, SecretKey};
+use serde::{Deserialize, Serialize};
+
+use crate::blockchain::{AccountState, Block, BlockHeader, BlockSignature, ChainParams};
+use crate::clock::Clock;
+use crate::error::{ConfigError, ConsensusError, Result};
+use crate::hash::Hash256;
+
+mod poa;
+mod pos;
+mod pow;
+
+pub use poa::ProofOfAuthority;
+pub use pos::{DoubleSignEvidence, ProofOfStake, SLOT_SECONDS};
+pub use pow::{PowAlgorithm, ProofOfWork, MAX_DIFFICULTY};
+
+// How far ahead of the local clock a new block's timestamp may be, in seconds
+pub const MAX_FUTURE_DRIFT: u64 = 2 * 60 * 60;
+
+// What a block at a given height has to carry to be accepted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SealRequirement {
+    // A hash with this many leading zero hex digits
+    Work { difficulty: usize, algorithm: PowAlgorithm },
+    // A signature from this authority (hex-encoded ed25519 public key)
+    Signature { signer: String },
+}
+
+// Which engine a chain runs, as recorded in its chain parameters
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(tag = "engine", rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+pub enum ConsensusConfig {
+    #[default]
+    ProofOfWork,
+    // Hex-encoded ed25519 public keys of the signers, which take turns by height
+    ProofOfAuthority { signers: Vec<String> },
+    // Hex-encoded ed25519 public keys of the validators that propose blocks until something is staked
+    ProofOfStake { genesis_validators: Vec<String> },
+}
+
+impl ConsensusConfig {
+    pub fn is_proof_of_work(&self) -> bool {
+        *self == ConsensusConfig::ProofOfWork
+    }
+}
+
+// Node-local resources a block is sealed with
+#[derive(Debug, Clone, Copy)]
+pub struct SealContext<'a> {
+    // Key for signature-based engines
+    pub signer: Option<&'a Keypair>,
+    // Worker threads for proof-of-work mining
+    pub threads: usize,
+    // Time that proof-of-work mining rolls block timestamps forward to
+    pub clock: &'a dyn Clock,
+}
+
+// A consensus engine decides how blocks are sealed, which seals are valid and how much
+// a block counts towards fork choice. Seals are only checked on blocks above genesis.
+// `parent` is the header of the block's parent and `state` the account state the block builds on.
+pub trait Consensus: Debug + Send + Sync {
+    // Make `block` acceptable at its height, once its transactions and header are final
+    fn seal(&self, block: &mut Block, parent: &BlockHeader, state: &AccountState, context: &SealContext) -> Result<(), ConsensusError>;
+
+    // `state` is None when the parent state is unknown because history below was pruned
+    fn verify_seal(&self, block: &Block, parent: &BlockHeader, state: Option<&AccountState>) -> bool;
+
+    // Contribution of a block to its chain's total weight; the heaviest valid chain wins
+    fn block_weight(&self, block: &Block) -> u128;
+
+    fn requirement(&self, block: &Block, parent: &BlockHeader, state: &AccountState) -> SealRequirement;
+
+    // Seconds a new block's timestamp may run ahead of the local clock
+    fn max_future_drift(&self) -> u64 {
+        MAX_FUTURE_DRIFT
+    }
+}
+
+pub fn engine(params: &ChainParams) -> Box<dyn Consensus> {
+    match &params.consensus {
+        ConsensusConfig::ProofOfWork => Box::new(ProofOfWork::new(params.difficulty, params.pow_algorithm)),
+        ConsensusConfig::ProofOfAuthority { signers } => Box::new(ProofOfAuthority::new(signers.clone())),
+        ConsensusConfig::ProofOfStake { genesis_validators } => Box::new(ProofOfStake::new(genesis_validators.clone())),
+    }
+}
+
+// Sealing engines sign the hex form of the block hash
+pub fn sign_block_hash(keypair: &Keypair, hash: &Hash256) -> String {
+    hex::encode(keypair.sign(hash.to_string().as_bytes()))
+}
+
+pub fn verify_block_signature(hash: &Hash256, signature: &BlockSignature) -> bool {
+    let public_key = hex::decode(&signature.signer).ok().and_then(|bytes| PublicKey::decode(&bytes).ok());
+    let sig = hex::decode(&signature.signature).ok();
+    match (public_key, sig) {
+        (Some(public_key), Some(sig)) => public_key.verify(hash.to_string().as_bytes(), &sig),
+        _ => false,
+    }
+}
+
+// Whether `key` is a hex-encoded ed25519 public key, as validators and signers are named by
+pub fn is_public_key(key: &str) -> bool {
+    hex::decode(key).ok().map(|bytes| PublicKey::decode(&bytes).is_ok()).unwrap_or(false)
+}
+
+pub fn public_key_hex(keypair: &Keypair) -> String {
+    hex::encode(keypair.public().encode())
+}
+
+// Read a signing key stored as a hex-encoded ed25519 secret, generating and saving one if the file does not exist
+pub fn load_or_create_key<P: AsRef<Path>>(path: P) -> Result<Keypair> {
+    let path = path.as_ref();
+    let invalid = |reason: String| ConfigError::InvalidKeyFile {
+        path: path.display().to_string(),
+        reason,
+    };
+
+    match fs::read_to_string(path) {
+        Ok(contents) => {
+            let bytes = hex::decode(contents.trim()).map_err(|e| invalid(e.to_string()))?;
+            let secret = SecretKey::from_bytes(bytes).map_err(|e| invalid(e.to_string()))?;
+            Ok(Keypair::from(secret))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let keypair = Keypair::generate();
+            crate::storage::write_atomic(path, hex::encode(keypair.secret().as_ref()).as_bytes())?;
+            Ok(keypair)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
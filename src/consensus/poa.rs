@@ -0,0 +1,67 @@
+use super::{public_key_hex, sign_block_hash, verify_block_signature, Consensus, SealContext, SealRequirement};
+use crate::blockchain::{AccountState, Block, BlockHeader, BlockSignature};
+use crate::error::ConsensusError;
+
+// Proof of authority: a fixed list of signers takes turns, the block at height h being
+// signed by signer h mod n. No work is done; the signature over the block hash is the seal.
+#[derive(Debug, Clone)]
+pub struct ProofOfAuthority {
+    signers: Vec<String>,
+}
+
+impl ProofOfAuthority {
+    pub fn new(signers: Vec<String>) -> Self {
+        ProofOfAuthority { signers }
+    }
+
+    fn signer_at(&self, height: u64) -> Option<&String> {
+        if self.signers.is_empty() {
+            return None;
+        }
+        self.signers.get((height % self.signers.len() as u64) as usize)
+    }
+}
+
+impl Consensus for ProofOfAuthority {
+    fn seal(&self, block: &mut Block, _parent: &BlockHeader, _state: &AccountState, context: &SealContext) -> Result<(), ConsensusError> {
+        let keypair = context.signer.ok_or(ConsensusError::MissingSigner {
+            engine: "Proof of authority",
+        })?;
+        let expected = self.signer_at(block.index).ok_or(ConsensusError::NoSigners)?;
+
+        let public_key = public_key_hex(keypair);
+        if &public_key != expected {
+            return Err(ConsensusError::NotScheduled {
+                height: block.index,
+                expected: expected.clone(),
+                actual: public_key,
+            });
+        }
+
+        block.hash = block.calculate_hash();
+        block.signature = Some(BlockSignature {
+            signer: public_key,
+            signature: sign_block_hash(keypair, &block.hash),
+        });
+        Ok(())
+    }
+
+    fn verify_seal(&self, block: &Block, _parent: &BlockHeader, _state: Option<&AccountState>) -> bool {
+        match &block.signature {
+            Some(signature) => {
+                self.signer_at(block.index) == Some(&signature.signer) && verify_block_signature(&block.hash, signature)
+            }
+            None => false,
+        }
+    }
+
+    fn block_weight(&self, _block: &Block) -> u128 {
+        1
+    }
+
+    fn requirement(&self, block: &Block, _parent: &BlockHeader, _state: &AccountState) -> SealRequirement {
+        SealRequirement::Signature {
+            signer: self.signer_at(block.index).cloned().unwrap_or_default(),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use sha2::{Digest, Sha256};
+
+use super::{public_key_hex, sign_block_hash, verify_block_signature, Consensus, SealContext, SealRequirement};
+use crate::blockchain::{AccountState, Block, BlockHeader, BlockSignature};
+use crate::error::ConsensusError;
+use crate::hash::Hash256;
+
+// Length of a proposer slot, in seconds
+pub const SLOT_SECONDS: u64 = 30;
+
+// Proof of stake: accounts lock funds with staking transactions, and the proposer of each
+// block is drawn from the validators in proportion to stake, using a seed derived from the
+// parent hash, height and slot so every node computes the same leader. Slots count
+// SLOT_SECONDS from the parent's timestamp, so when a leader stays offline the next slot
+// draws again and the chain moves on. Until anything is staked the genesis validators take
+// part with equal weight. Slashed validators never propose again.
+#[derive(Debug, Clone)]
+pub struct ProofOfStake {
+    genesis_validators: Vec<String>,
+}
+
+impl ProofOfStake {
+    pub fn new(genesis_validators: Vec<String>) -> Self {
+        ProofOfStake { genesis_validators }
+    }
+
+    // Slot a block falls in, counted from its parent. Blocks timestamped before their
+    // parent are in no slot.
+    pub fn slot(block: &Block, parent: &BlockHeader) -> Option<u64> {
+        block.timestamp.checked_sub(parent.timestamp).map(|elapsed| elapsed / SLOT_SECONDS)
+    }
+
+    // Validator entitled to propose the block at `height` on top of `previous_hash` in `slot`
+    pub fn leader(&self, height: u64, previous_hash: &Hash256, slot: u64, state: &AccountState) -> Option<String> {
+        let mut validators: Vec<(&String, f64)> = state
+            .stakes
+            .iter()
+            .filter(|(validator, &amount)| amount > 0.0 && !state.slashed.contains(*validator))
+            .map(|(validator, &amount)| (validator, amount as f64))
+            .collect();
+        if validators.is_empty() {
+            validators = self.genesis_validators
+                .iter()
+                .filter(|validator| !state.slashed.contains(*validator))
+                .map(|validator| (validator, 1.0))
+                .collect();
+        }
+
+        let total: f64 = validators.iter().map(|(_, weight)| weight).sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{}:{}:{}", previous_hash, height, slot).as_bytes());
+        let seed = hasher.finalize();
+        let draw = u64::from_be_bytes(seed[..8].try_into().unwrap()) as f64 / (u64::MAX as f64 + 1.0) * total;
+
+        let mut cumulative = 0.0;
+        for (validator, weight) in &validators {
+            cumulative += weight;
+            if draw < cumulative {
+                return Some(validator.to_string());
+            }
+        }
+        validators.last().map(|(validator, _)| validator.to_string())
+    }
+}
+
+impl Consensus for ProofOfStake {
+    // Sealed in the slot the clock is in now
+    fn seal(&self, block: &mut Block, parent: &BlockHeader, state: &AccountState, context: &SealContext) -> Result<(), ConsensusError> {
+        let keypair = context.signer.ok_or(ConsensusError::MissingSigner {
+            engine: "Proof of stake",
+        })?;
+        block.timestamp = context.clock.now().max(parent.timestamp);
+        let slot = Self::slot(block, parent).unwrap_or_default();
+        let leader = self
+            .leader(block.index, &block.previous_hash, slot, state)
+            .ok_or(ConsensusError::NoSigners)?;
+
+        let public_key = public_key_hex(keypair);
+        if public_key != leader {
+            return Err(ConsensusError::NotScheduled {
+                height: block.index,
+                expected: leader,
+                actual: public_key,
+            });
+        }
+
+        block.hash = block.calculate_hash();
+        block.signature = Some(BlockSignature {
+            signer: public_key,
+            signature: sign_block_hash(keypair, &block.hash),
+        });
+        Ok(())
+    }
+
+    // Without the parent state (pruned history) only the signature itself can be checked
+    fn verify_seal(&self, block: &Block, parent: &BlockHeader, state: Option<&AccountState>) -> bool {
+        let signature = match &block.signature {
+            Some(signature) => signature,
+            None => return false,
+        };
+        let slot = match Self::slot(block, parent) {
+            Some(slot) => slot,
+            None => return false,
+        };
+
+        if let Some(state) = state {
+            if self.leader(block.index, &block.previous_hash, slot, state).as_ref() != Some(&signature.signer) {
+                return false;
+            }
+        }
+        verify_block_signature(&block.hash, signature)
+    }
+
+    fn block_weight(&self, _block: &Block) -> u128 {
+        1
+    }
+
+    fn requirement(&self, block: &Block, parent: &BlockHeader, state: &AccountState) -> SealRequirement {
+        let slot = Self::slot(block, parent).unwrap_or_default();
+        SealRequirement::Signature {
+            signer: self.leader(block.index, &block.previous_hash, slot, state).unwrap_or_default(),
+        }
+    }
+
+    // A block may not claim a slot before it starts, beyond the clock skew between nodes
+    fn max_future_drift(&self) -> u64 {
+        SLOT_SECONDS / 2
+    }
+}
+
+// Two different blocks at the same height signed by the same validator. Submitted on chain,
+// it slashes the validator's whole stake.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+pub struct DoubleSignEvidence {
+    pub first: BlockHeader,
+    pub second: BlockHeader,
+}
+
+impl DoubleSignEvidence {
+    // Evidence if the two headers are conflicting blocks signed by the same validator
+    pub fn from_headers(first: BlockHeader, second: BlockHeader) -> Option<Self> {
+        let evidence = DoubleSignEvidence { first, second };
+        if evidence.is_valid() {
+            Some(evidence)
+        } else {
+            None
+        }
+    }
+
+    pub fn offender(&self) -> Option<&str> {
+        self.first.signature.as_ref().map(|signature| signature.signer.as_str())
+    }
+
+    // Both headers have to hash to the signed hash, or the height they claim would be
+    // unrelated to what was signed. Version 0 hashes cover the body, so those cannot be checked.
+    pub fn is_valid(&self) -> bool {
+        let (first, second) = match (&self.first.signature, &self.second.signature) {
+            (Some(first), Some(second)) => (first, second),
+            _ => return false,
+        };
+        let committed = |header: &BlockHeader| header.version >= 1 && header.calculate_hash() == header.hash;
+
+        committed(&self.first)
+            && committed(&self.second)
+            && self.first.index == self.second.index
+            && self.first.hash != self.second.hash
+            && first.signer == second.signer
+            && verify_block_signature(&self.first.hash, first)
+            && verify_block_signature(&self.second.hash, second)
+    }
+}
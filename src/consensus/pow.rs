@@ -0,0 +1,129 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{Consensus, SealContext, SealRequirement};
+use crate::blockchain::{AccountState, Block, BlockHeader};
+use crate::error::{ConfigError, ConsensusError};
+use crate::hash::Hash256;
+
+// Most leading zero hex digits a 32-byte proof-of-work hash can have
+pub const MAX_DIFFICULTY: usize = 64;
+
+// Hash function proof of work is measured with. It is applied to the block id, so seals can
+// be checked from headers alone and the choice never changes how blocks are identified.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowAlgorithm {
+    // The block id itself, as on legacy chains
+    #[default]
+    Sha256,
+    DoubleSha256,
+    Blake3,
+    // Memory-hard (1 MiB per hash), to keep mining on general-purpose hardware
+    Scrypt,
+}
+
+impl PowAlgorithm {
+    pub fn is_default(&self) -> bool {
+        *self == PowAlgorithm::Sha256
+    }
+
+    // Proof-of-work hash of a block id
+    pub fn pow_hash(&self, block_hash: &Hash256) -> [u8; 32] {
+        let id = *block_hash.as_bytes();
+
+        match self {
+            PowAlgorithm::Sha256 => id,
+            PowAlgorithm::DoubleSha256 => Sha256::digest(id).into(),
+            PowAlgorithm::Blake3 => *blake3::hash(&id).as_bytes(),
+            PowAlgorithm::Scrypt => {
+                let params = scrypt::Params::new(10, 8, 1, 32).expect("Valid scrypt parameters");
+                let mut output = [0u8; 32];
+                scrypt::scrypt(&id, b"rustchain-pow", &params, &mut output).expect("Valid scrypt output length");
+                output
+            }
+        }
+    }
+
+    // Whether the proof-of-work hash of a block id starts with `difficulty` zero hex digits
+    pub fn meets_difficulty(&self, block_hash: &Hash256, difficulty: usize) -> bool {
+        let hash = self.pow_hash(block_hash);
+
+        (0..difficulty).all(|digit| {
+            let byte = match hash.get(digit / 2) {
+                Some(byte) => byte,
+                None => return false,
+            };
+            let nibble = if digit % 2 == 0 { byte >> 4 } else { byte & 0x0f };
+            nibble == 0
+        })
+    }
+}
+
+impl FromStr for PowAlgorithm {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sha256" => Ok(PowAlgorithm::Sha256),
+            "double-sha256" => Ok(PowAlgorithm::DoubleSha256),
+            "blake3" => Ok(PowAlgorithm::Blake3),
+            "scrypt" => Ok(PowAlgorithm::Scrypt),
+            other => Err(ConfigError::invalid(
+                "proof-of-work hash",
+                other,
+                "expected sha256, double-sha256, blake3 or scrypt",
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PowAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PowAlgorithm::Sha256 => write!(f, "sha256"),
+            PowAlgorithm::DoubleSha256 => write!(f, "double-sha256"),
+            PowAlgorithm::Blake3 => write!(f, "blake3"),
+            PowAlgorithm::Scrypt => write!(f, "scrypt"),
+        }
+    }
+}
+
+// Proof of work with a fixed number of leading zero hex digits in the chosen hash
+#[derive(Debug, Clone)]
+pub struct ProofOfWork {
+    difficulty: usize,
+    algorithm: PowAlgorithm,
+}
+
+impl ProofOfWork {
+    pub fn new(difficulty: usize, algorithm: PowAlgorithm) -> Self {
+        ProofOfWork { difficulty, algorithm }
+    }
+}
+
+impl Consensus for ProofOfWork {
+    fn seal(&self, block: &mut Block, _parent: &BlockHeader, _state: &AccountState, context: &SealContext) -> Result<(), ConsensusError> {
+        block.mine_block(self.difficulty, self.algorithm, context.threads, context.clock);
+        Ok(())
+    }
+
+    fn verify_seal(&self, block: &Block, _parent: &BlockHeader, _state: Option<&AccountState>) -> bool {
+        self.algorithm.meets_difficulty(&block.hash, self.difficulty)
+    }
+
+    // Expected number of hashes needed to find a block
+    fn block_weight(&self, _block: &Block) -> u128 {
+        16u128.saturating_pow(self.difficulty as u32)
+    }
+
+    fn requirement(&self, _block: &Block, _parent: &BlockHeader, _state: &AccountState) -> SealRequirement {
+        SealRequirement::Work {
+            difficulty: self.difficulty,
+            algorithm: self.algorithm,
+        }
+    }
+}
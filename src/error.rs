@@ -0,0 +1,156 @@
+use std::io;
+
+use thiserror::Error;
+
+use crate::hash::Hash256;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+// Every failure the node reports, grouped by where it comes from
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+    #[error(transparent)]
+    Consensus(#[from] ConsensusError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Network(#[from] NetworkError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    // A lookup for something the node does not know about
+    #[error("{0} not found")]
+    NotFound(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Storage(StorageError::Io(e))
+    }
+}
+
+// A chain, block or transaction that breaks the rules of its network
+#[derive(Debug, Error)]
+pub enum ValidationError {
+    #[error("Chain has no genesis block")]
+    NoGenesis,
+    #[error("Genesis block is invalid")]
+    InvalidGenesis,
+    #[error("Chain has a different genesis block")]
+    GenesisMismatch,
+    #[error("Block {found} at height {height} conflicts with checkpoint {expected}")]
+    CheckpointConflict { height: u64, expected: Hash256, found: Hash256 },
+    #[error("Block height {height} is out of sequence")]
+    HeightOutOfSequence { height: u64 },
+    #[error("Block {hash} has an unknown header format")]
+    UnknownHeaderFormat { hash: Hash256 },
+    #[error("Header {height} cannot be checked without its block body")]
+    UnverifiableHeader { height: u64 },
+    #[error("Hash of block {height} is invalid")]
+    InvalidHash { height: u64 },
+    #[error("Transactions root of block {hash} is invalid")]
+    InvalidTxRoot { hash: Hash256 },
+    #[error("Block {hash} holds transaction {txid} more than once")]
+    DuplicateTransaction { hash: Hash256, txid: Hash256 },
+    #[error("Block {hash} is timestamped {timestamp}, too far in the future")]
+    TimestampInFuture { hash: Hash256, timestamp: u64 },
+    #[error("Seal of block {hash} is invalid")]
+    InvalidSeal { hash: Hash256 },
+    #[error("Block {height} does not link to the previous block")]
+    BrokenLink { height: u64 },
+    #[error("Block {hash} does not extend the current tip")]
+    NotOnTip { hash: Hash256 },
+    #[error("Block {hash} holds invalid double-sign evidence")]
+    InvalidEvidence { hash: Hash256 },
+    #[error("Block {hash} does not pay exactly one mining reward")]
+    InvalidCoinbase { hash: Hash256 },
+    #[error("Mining rewards can only be paid by blocks")]
+    UnexpectedCoinbase,
+    #[error("Expected nonce {expected} from {address}, got {found}")]
+    InvalidNonce { address: String, expected: u64, found: u64 },
+    #[error("Amount {amount} is negative or not finite")]
+    InvalidAmount { amount: f32 },
+    #[error("{address} has {available} to spend, not {amount}")]
+    InsufficientFunds { address: String, amount: f32, available: f32 },
+    #[error("Cannot stake with {validator}, which is not a validator public key")]
+    InvalidValidator { validator: String },
+    #[error("Headers are not two blocks at one height signed by the same validator")]
+    NotDoubleSign,
+    #[error("Snapshot is for block {found}, expected trusted block {expected}")]
+    UntrustedSnapshot { expected: Hash256, found: Hash256 },
+    #[error("Snapshot headers do not end at the snapshot block")]
+    SnapshotHeadersMismatch,
+    #[error("Block {hash} is not on the header chain")]
+    UnknownBlock { hash: Hash256 },
+    #[error("Merkle proof of transaction {txid} does not match its block")]
+    InvalidProof { txid: Hash256 },
+}
+
+// Failure to seal a block
+#[derive(Debug, Error)]
+pub enum ConsensusError {
+    #[error("{engine} needs a signer key to seal blocks")]
+    MissingSigner { engine: &'static str },
+    #[error("There are no signers or validators to seal blocks")]
+    NoSigners,
+    #[error("Block {height} is for {expected} to seal now, not {actual}")]
+    NotScheduled { height: u64, expected: String, actual: String },
+}
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Malformed JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Malformed binary data: {0}")]
+    Binary(String),
+    #[error("{0} checksum mismatch")]
+    ChecksumMismatch(&'static str),
+    #[error("Unsupported chain file version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Expected block at height {expected}, got {found}")]
+    UnexpectedHeight { expected: u64, found: u64 },
+    #[error("{path} holds an invalid chain: {source}")]
+    InvalidChain { path: String, source: ValidationError },
+    #[error("{path} does not match the in-memory chain")]
+    SavedChainMismatch { path: String },
+    #[error("No state available at height {0}")]
+    StateUnavailable(u64),
+    #[error("Corrupt block store: {0}")]
+    Corrupt(String),
+}
+
+#[derive(Debug, Error)]
+pub enum NetworkError {
+    #[error("Invalid address {address}: {reason}")]
+    InvalidAddress { address: String, reason: String },
+    #[error("Failed to listen on {address}: {reason}")]
+    Listen { address: String, reason: String },
+    #[error("Failed to dial {address}: {reason}")]
+    Dial { address: String, reason: String },
+    #[error("Failed to set up the network transport: {0}")]
+    Transport(String),
+}
+
+// Bad settings or inputs from the operator
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Invalid {what} '{value}': {reason}")]
+    InvalidValue { what: &'static str, value: String, reason: String },
+    #[error("Invalid key file {path}: {reason}")]
+    InvalidKeyFile { path: String, reason: String },
+    #[error("{0} already exists")]
+    AlreadyExists(String),
+}
+
+impl ConfigError {
+    pub fn invalid<V: ToString, R: ToString>(what: &'static str, value: V, reason: R) -> Self {
+        ConfigError::InvalidValue {
+            what,
+            value: value.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
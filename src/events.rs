@@ -0,0 +1,85 @@
+use std::fmt;
+
+use libp2p::PeerId;
+use tokio::sync::broadcast;
+
+use crate::blockchain::{Block, BlockHeader, Transaction};
+use crate::hash::Hash256;
+
+// Events kept for each subscriber; one that falls further behind misses the oldest
+const EVENT_BUFFER: usize = 256;
+
+// Something that happened to the chain, the mempool or the node's peers
+#[derive(Debug, Clone)]
+pub enum Event {
+    // A block was mined here or accepted from a peer on top of the tip
+    BlockConnected(Block),
+    // The chain switched to another branch, through fork choice, a load or a snapshot
+    // bootstrap. Its first `common` blocks were kept, the `disconnected` blocks above them
+    // dropped, and the `connected` blocks, in height order, now lead up to `tip`. Both runs
+    // come in this one event rather than block by block, so a long replacement cannot push
+    // them out of a subscriber's buffer. Blocks the node holds no body for come as headers only.
+    ChainReplaced {
+        common: u64,
+        disconnected: Vec<Block>,
+        connected: Vec<Block>,
+        tip: BlockHeader,
+    },
+    // A transaction entered the mempool
+    TxAccepted { id: Hash256, transaction: Transaction },
+    // A pending transaction left the mempool without being confirmed
+    TxEvicted { id: Hash256 },
+    PeerConnected(PeerId),
+    PeerDisconnected(PeerId),
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::BlockConnected(block) => write!(f, "Block {} connected at height {}", block.hash, block.index),
+            Event::ChainReplaced { common, disconnected, connected, tip } => write!(
+                f,
+                "Chain replaced keeping {} block(s): {} disconnected, {} connected, tip {} at height {}",
+                common, disconnected.len(), connected.len(), tip.hash, tip.index,
+            ),
+            Event::TxAccepted { id, transaction } => write!(
+                f,
+                "Transaction {} accepted: {} -> {} ({})",
+                id, transaction.sender, transaction.receiver, transaction.amount,
+            ),
+            Event::TxEvicted { id } => write!(f, "Transaction {} evicted", id),
+            Event::PeerConnected(peer) => write!(f, "Peer {} connected", peer),
+            Event::PeerDisconnected(peer) => write!(f, "Peer {} disconnected", peer),
+        }
+    }
+}
+
+// Fans events out to every subscriber. Clones share one bus, so the chain, the network
+// layer and anything watching them see the same stream in the order it was emitted.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        EventBus { sender }
+    }
+
+    // Nobody listening is fine; the event is simply dropped
+    pub fn emit(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    // Every event emitted from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,352 @@
+use std::collections::VecDeque;
+
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use crate::blockchain::{
+    AddressHistory, Block, BlockHeader, BlockOutcome, Blockchain, ChainStats, ChainTip, Snapshot, Transaction, TransactionStatus, TxKind,
+};
+use crate::error::{ConsensusError, Result, ValidationError};
+use crate::events::{Event, EventBus};
+use crate::hash::Hash256;
+use crate::light::TxProof;
+use crate::storage::ChainFileFormat;
+
+// Requests queued for the chain task before callers have to wait
+const COMMAND_BUFFER: usize = 100;
+
+// Spendable and staked funds of an address
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Balance {
+    pub balance: f32,
+    pub staked: f32,
+}
+
+// Outline of a whole chain, such as one read back from a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainSummary {
+    pub network_id: String,
+    pub blocks: usize,
+    pub tip: Hash256,
+}
+
+// Blocks pruned by a prune request, and the height bodies are now kept from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PruneOutcome {
+    pub pruned: u64,
+    pub pruned_height: u64,
+}
+
+// Requests served by the chain task, each with a channel for its reply
+enum BlockchainCommand {
+    AddTransaction(Transaction, oneshot::Sender<Result<Hash256>>),
+    ReceiveTransaction(Transaction, oneshot::Sender<Result<bool>>),
+    Mine(String, oneshot::Sender<Result<Block>>),
+    Balance(String, oneshot::Sender<Balance>),
+    BlockByHeight(u64, oneshot::Sender<Option<Block>>),
+    BlockByHash(Hash256, oneshot::Sender<Option<Block>>),
+    Blocks(u64, usize, oneshot::Sender<Vec<Block>>),
+    Tip(oneshot::Sender<ChainTip>),
+    Pending(oneshot::Sender<Vec<Transaction>>),
+    Stats(oneshot::Sender<ChainStats>),
+    Transaction(Hash256, oneshot::Sender<Option<TransactionStatus>>),
+    History(String, usize, usize, oneshot::Sender<AddressHistory>),
+    Validate(oneshot::Sender<Result<(), ValidationError>>),
+    Save(String, ChainFileFormat, bool, oneshot::Sender<Result<()>>),
+    Load(String, oneshot::Sender<Result<()>>),
+    CheckFile(String, oneshot::Sender<Result<ChainSummary>>),
+    ExportSnapshot(String, Option<u64>, oneshot::Sender<Result<Snapshot>>),
+    Bootstrap(String, Hash256, oneshot::Sender<Result<u64>>),
+    Prune(u64, oneshot::Sender<Result<PruneOutcome>>),
+    ReplaceChain(Vec<Block>, oneshot::Sender<Result<Option<Hash256>>>),
+    SubmitBlock(Block, oneshot::Sender<Result<BlockOutcome>>),
+    Headers(u64, usize, oneshot::Sender<Vec<BlockHeader>>),
+    ProveTransactions(String, oneshot::Sender<Vec<TxProof>>),
+}
+
+// Cloneable access to a chain owned by a single task. Requests are served one at a time
+// in the order they arrive, so callers never share the chain or lock it themselves.
+// Blocks are sealed on a blocking thread from a template, so other requests keep being
+// served while a block is mined.
+#[derive(Clone)]
+pub struct BlockchainHandle {
+    commands: mpsc::Sender<BlockchainCommand>,
+    events: EventBus,
+}
+
+impl BlockchainHandle {
+    // Move the chain onto its own task, which runs until every handle is dropped
+    pub fn spawn(blockchain: Blockchain) -> Self {
+        let (commands, receiver) = mpsc::channel(COMMAND_BUFFER);
+        let events = blockchain.events().clone();
+        tokio::spawn(run(blockchain, receiver));
+        BlockchainHandle { commands, events }
+    }
+
+    // Queue a transaction of any kind, returning its id
+    pub async fn add_transaction(&self, transaction: Transaction) -> Result<Hash256> {
+        self.request(|reply| BlockchainCommand::AddTransaction(transaction, reply)).await
+    }
+
+    // Queue a transaction relayed by a peer as it is, returning whether it was new
+    pub async fn receive_transaction(&self, transaction: Transaction) -> Result<bool> {
+        self.request(|reply| BlockchainCommand::ReceiveTransaction(transaction, reply)).await
+    }
+
+    // Seal the pending transactions into a new block paying `address`, returning the block
+    pub async fn mine(&self, address: &str) -> Result<Block> {
+        self.request(|reply| BlockchainCommand::Mine(address.to_string(), reply)).await
+    }
+
+    pub async fn balance(&self, address: &str) -> Balance {
+        self.request(|reply| BlockchainCommand::Balance(address.to_string(), reply)).await
+    }
+
+    pub async fn block_by_height(&self, height: u64) -> Option<Block> {
+        self.request(|reply| BlockchainCommand::BlockByHeight(height, reply)).await
+    }
+
+    pub async fn block_by_hash(&self, hash: Hash256) -> Option<Block> {
+        self.request(|reply| BlockchainCommand::BlockByHash(hash, reply)).await
+    }
+    
+    // Up to `limit` consecutive blocks starting at height `from`
+    pub async fn blocks(&self, from: u64, limit: usize) -> Vec<Block> {
+        self.request(|reply| BlockchainCommand::Blocks(from, limit, reply)).await
+    }
+    
+    pub async fn tip(&self) -> ChainTip {
+        self.request(BlockchainCommand::Tip).await
+    }
+    
+    pub async fn pending_transactions(&self) -> Vec<Transaction> {
+        self.request(BlockchainCommand::Pending).await
+    }
+    
+    pub async fn stats(&self) -> ChainStats {
+        self.request(BlockchainCommand::Stats).await
+    }
+    
+    pub async fn transaction(&self, id: Hash256) -> Option<TransactionStatus> {
+        self.request(|reply| BlockchainCommand::Transaction(id, reply)).await
+    }
+
+    pub async fn history(&self, address: &str, offset: usize, limit: usize) -> AddressHistory {
+        self.request(|reply| BlockchainCommand::History(address.to_string(), offset, limit, reply)).await
+    }
+
+    pub async fn validate(&self) -> Result<(), ValidationError> {
+        self.request(BlockchainCommand::Validate).await
+    }
+
+    // Save the chain and mempool, then read the file back and compare if `verify` is set
+    pub async fn save(&self, path: &str, format: ChainFileFormat, verify: bool) -> Result<()> {
+        self.request(|reply| BlockchainCommand::Save(path.to_string(), format, verify, reply)).await
+    }
+
+    // Replace the chain with a saved one, keeping the block store in step
+    pub async fn load(&self, path: &str) -> Result<()> {
+        self.request(|reply| BlockchainCommand::Load(path.to_string(), reply)).await
+    }
+
+    // Check a saved chain against this chain's network without adopting it
+    pub async fn check_file(&self, path: &str) -> Result<ChainSummary> {
+        self.request(|reply| BlockchainCommand::CheckFile(path.to_string(), reply)).await
+    }
+
+    // Write a state snapshot at `height`, or at the tip
+    pub async fn export_snapshot(&self, path: &str, height: Option<u64>) -> Result<Snapshot> {
+        self.request(|reply| BlockchainCommand::ExportSnapshot(path.to_string(), height, reply)).await
+    }
+
+    // Replace the chain with one started from a trusted snapshot, returning its height
+    pub async fn bootstrap(&self, path: &str, trusted_hash: Hash256) -> Result<u64> {
+        self.request(|reply| BlockchainCommand::Bootstrap(path.to_string(), trusted_hash, reply)).await
+    }
+
+    pub async fn prune(&self, depth: u64) -> Result<PruneOutcome> {
+        self.request(|reply| BlockchainCommand::Prune(depth, reply)).await
+    }
+
+    // Run fork choice on a chain from a peer, returning the new tip if it was adopted
+    pub async fn replace_chain(&self, candidate: Vec<Block>) -> Result<Option<Hash256>> {
+        self.request(|reply| BlockchainCommand::ReplaceChain(candidate, reply)).await
+    }
+
+    // Connect a block from a peer or run fork choice on it
+    pub async fn submit_block(&self, block: Block) -> Result<BlockOutcome> {
+        self.request(|reply| BlockchainCommand::SubmitBlock(block, reply)).await
+    }
+    
+    // Up to `limit` headers starting at `from`
+    pub async fn headers(&self, from: u64, limit: usize) -> Vec<BlockHeader> {
+        self.request(|reply| BlockchainCommand::Headers(from, limit, reply)).await
+    }
+    
+    // Merkle proofs of the provable confirmed transactions touching `address`
+    pub async fn prove_transactions(&self, address: &str) -> Vec<TxProof> {
+        self.request(|reply| BlockchainCommand::ProveTransactions(address.to_string(), reply)).await
+    }
+    
+    // Every chain, mempool and peer event from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+    
+    // The chain's event bus, for layers that report events of their own
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    async fn request<T>(&self, command: impl FnOnce(oneshot::Sender<T>) -> BlockchainCommand) -> T {
+        let (reply, response) = oneshot::channel();
+        // The task outlives every handle, so it is only gone if it panicked
+        self.commands.send(command(reply)).await.expect("Chain task stopped");
+        response.await.expect("Chain task stopped")
+    }
+}
+
+// A block being sealed on a blocking thread, and the caller waiting for it
+struct Mining {
+    task: JoinHandle<Result<Block, ConsensusError>>,
+    reply: oneshot::Sender<Result<Block>>,
+}
+
+async fn run(mut chain: Blockchain, mut commands: mpsc::Receiver<BlockchainCommand>) {
+    let mut mining: Option<Mining> = None;
+    // Mine requests that arrived while a block was being sealed, each mined on the tip of its turn
+    let mut mine_queue = VecDeque::new();
+    
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(BlockchainCommand::Mine(address, reply)) => mine_queue.push_back((address, reply)),
+                Some(command) => serve(&mut chain, command),
+                None => break,
+            },
+            sealed = async { (&mut mining.as_mut().expect("Mining in progress").task).await }, if mining.is_some() => {
+                let reply = mining.take().expect("Mining in progress").reply;
+                // A panic while sealing is a bug, so it takes the chain task down as it would have inline
+                let sealed = sealed.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+                
+                // The tip may have moved while sealing, in which case the block is refused as stale
+                let result = sealed
+                    .map_err(Into::into)
+                    .and_then(|block| chain.accept_block(block.clone()).map(|_| block));
+                let _ = reply.send(result);
+            }
+        }
+        
+        if mining.is_none() {
+            if let Some((address, reply)) = mine_queue.pop_front() {
+                let template = chain.block_template(&address);
+                mining = Some(Mining {
+                    task: tokio::task::spawn_blocking(move || template.seal()),
+                    reply,
+                });
+            }
+        }
+    }
+}
+
+fn serve(chain: &mut Blockchain, command: BlockchainCommand) {
+    match command {
+        BlockchainCommand::AddTransaction(transaction, reply) => {
+            let result = match transaction.kind {
+                TxKind::Transfer => chain.add_transaction(transaction.sender, transaction.receiver, transaction.amount),
+                TxKind::Stake => chain.add_stake(transaction.sender, transaction.receiver, transaction.amount),
+                TxKind::Evidence(evidence) => chain.submit_evidence(transaction.sender, *evidence),
+            };
+            let _ = reply.send(result);
+        }
+        BlockchainCommand::ReceiveTransaction(transaction, reply) => {
+            let _ = reply.send(chain.receive_transaction(transaction));
+        }
+        BlockchainCommand::Mine(..) => unreachable!("Mine requests are queued by the chain task"),
+        BlockchainCommand::Balance(address, reply) => {
+            let _ = reply.send(Balance {
+                balance: chain.get_balance(&address),
+                staked: chain.get_stake(&address),
+            });
+        }
+        BlockchainCommand::BlockByHeight(height, reply) => {
+            let _ = reply.send(chain.get_block(height).cloned());
+        }
+        BlockchainCommand::BlockByHash(hash, reply) => {
+            let _ = reply.send(chain.get_block_by_hash(&hash).cloned());
+        }
+        BlockchainCommand::Blocks(from, limit, reply) => {
+            let _ = reply.send(chain.get_blocks(from, limit).to_vec());
+        }
+        BlockchainCommand::Tip(reply) => {
+            let _ = reply.send(chain.get_tip());
+        }
+        BlockchainCommand::Pending(reply) => {
+            let _ = reply.send(chain.get_pending_transactions().to_vec());
+        }
+        BlockchainCommand::Stats(reply) => {
+            let _ = reply.send(chain.get_stats());
+        }
+        BlockchainCommand::Transaction(id, reply) => {
+            let _ = reply.send(chain.get_transaction(&id));
+        }
+        BlockchainCommand::History(address, offset, limit, reply) => {
+            let _ = reply.send(chain.get_history(&address, offset, limit));
+        }
+        BlockchainCommand::Validate(reply) => {
+            let _ = reply.send(chain.validate_chain());
+        }
+        BlockchainCommand::Save(path, format, verify, reply) => {
+            let result = chain
+                .save_to_disk(&path, true, format)
+                .and_then(|_| if verify { chain.verify_saved(&path) } else { Ok(()) });
+            let _ = reply.send(result);
+        }
+        BlockchainCommand::Load(path, reply) => {
+            // Keep persisting to the same block store, rewritten to match the loaded chain
+            let result = Blockchain::load_from_disk(&path, &chain.network_id, &chain.params)
+                .and_then(|loaded| chain.replace_with(loaded));
+            let _ = reply.send(result);
+        }
+        BlockchainCommand::CheckFile(path, reply) => {
+            let result = Blockchain::load_from_disk(&path, &chain.network_id, &chain.params).map(|loaded| ChainSummary {
+                network_id: loaded.network_id.clone(),
+                blocks: loaded.chain.len(),
+                tip: loaded.get_latest_block().hash,
+            });
+            let _ = reply.send(result);
+        }
+        BlockchainCommand::ExportSnapshot(path, height, reply) => {
+            let height = height.unwrap_or_else(|| chain.get_latest_block().index);
+            let _ = reply.send(chain.save_snapshot(&path, height));
+        }
+        BlockchainCommand::Bootstrap(path, trusted_hash, reply) => {
+            let result = Blockchain::load_snapshot(&path, &trusted_hash)
+                .and_then(|snapshot_chain| chain.replace_with(snapshot_chain))
+                .map(|_| chain.get_latest_block().index);
+            let _ = reply.send(result);
+        }
+        BlockchainCommand::Prune(depth, reply) => {
+            let result = chain.prune(depth).map(|pruned| PruneOutcome {
+                pruned,
+                pruned_height: chain.pruned_height(),
+            });
+            let _ = reply.send(result);
+        }
+        BlockchainCommand::ReplaceChain(candidate, reply) => {
+            let result = chain
+                .try_replace_chain(candidate)
+                .map(|adopted| adopted.then(|| chain.get_latest_block().hash));
+            let _ = reply.send(result);
+        }
+        BlockchainCommand::SubmitBlock(block, reply) => {
+            let _ = reply.send(chain.submit_block(block));
+        }
+        BlockchainCommand::Headers(from, limit, reply) => {
+            let _ = reply.send(chain.headers_from(from, limit));
+        }
+        BlockchainCommand::ProveTransactions(address, reply) => {
+            let _ = reply.send(chain.prove_transactions(&address));
+        }
+    }
+}
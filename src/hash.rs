@@ -0,0 +1,114 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+// A SHA-256 sized hash: block ids, block links and transaction ids.
+// Text formats carry it as 64 lowercase hex digits and binary formats as raw bytes.
+// The all-zero hash is written as "0", which legacy chains use as the genesis block's
+// parent, so their block hashes and file checksums are unchanged.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Hash256(pub [u8; 32]);
+
+impl Hash256 {
+    pub const ZERO: Hash256 = Hash256([0; 32]);
+
+    // SHA-256 of `data`
+    pub fn digest(data: &[u8]) -> Self {
+        Hash256(Sha256::digest(data).into())
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Self::ZERO
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("'{0}' is not a 64-digit hex hash")]
+pub struct ParseHashError(String);
+
+impl From<[u8; 32]> for Hash256 {
+    fn from(bytes: [u8; 32]) -> Self {
+        Hash256(bytes)
+    }
+}
+
+impl fmt::Display for Hash256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+impl fmt::Debug for Hash256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Hash256({})", self)
+    }
+}
+
+impl FromStr for Hash256 {
+    type Err = ParseHashError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "0" {
+            return Ok(Self::ZERO);
+        }
+
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(s, &mut bytes).map_err(|_| ParseHashError(s.to_string()))?;
+        Ok(Hash256(bytes))
+    }
+}
+
+impl Serialize for Hash256 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() {
+            serializer.serialize_bytes(&self.0)
+        } else if self.is_zero() {
+            serializer.serialize_str("0")
+        } else {
+            serializer.serialize_str(&self.to_string())
+        }
+    }
+}
+
+// Accepts either encoding regardless of the format, since buffered (flattened or untagged)
+// deserialization does not report whether the format is human readable
+impl<'de> Deserialize<'de> for Hash256 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(Hash256Visitor)
+    }
+}
+
+struct Hash256Visitor;
+
+impl<'de> Visitor<'de> for Hash256Visitor {
+    type Value = Hash256;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a 64-digit hex string or 32 bytes")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Hash256, E> {
+        value.parse().map_err(E::custom)
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Hash256, E> {
+        let bytes: [u8; 32] = value.try_into().map_err(|_| E::invalid_length(value.len(), &self))?;
+        Ok(Hash256(bytes))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Hash256, A::Error> {
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(i, &self))?;
+        }
+        Ok(Hash256(bytes))
+    }
+}
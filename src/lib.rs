@@ -0,0 +1,32 @@
+// Core of the rustchain node: the ledger, consensus engines, storage and networking.
+// The `blockchain` and `algo` binaries are front ends over this crate.
+
+pub mod algo;
+pub mod blockchain;
+pub mod clock;
+pub mod consensus;
+pub mod error;
+pub mod events;
+pub mod handle;
+pub mod hash;
+pub mod light;
+pub mod merkle;
+pub mod mining;
+pub mod notify;
+pub mod p2p;
+pub mod storage;
+
+pub use blockchain::{
+    AccountState, Block, BlockHeader, Blockchain, ChainParams, Transaction, TransactionStatus, TxKind,
+    DEFAULT_NETWORK_ID, TESTNET_NETWORK_ID,
+};
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use consensus::{Consensus, ConsensusConfig, PowAlgorithm};
+pub use error::{Error, Result};
+pub use events::{Event, EventBus};
+pub use handle::BlockchainHandle;
+pub use hash::Hash256;
+pub use light::{LightClient, TxProof};
+pub use notify::{Notifier, NotifyConfig};
+pub use p2p::{start_light_node, start_p2p_node, LightNode, P2P};
+pub use storage::{BlockStore, ChainFileFormat};
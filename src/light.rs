@@ -0,0 +1,187 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::blockchain::{check_header, Block, BlockHeader, ChainParams, Transaction};
+use crate::consensus::{self, Consensus};
+use crate::error::ValidationError;
+use crate::hash::Hash256;
+use crate::merkle::MerkleProof;
+
+// Headers below the tip asked for again on each sync, so headers from a peer on a short
+// fork still connect to ours
+const SYNC_OVERLAP: u64 = 6;
+
+// A confirmed transaction with the Merkle branch tying it to its block's transactions root
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxProof {
+    pub transaction: Transaction,
+    pub block_hash: Hash256,
+    pub height: u64,
+    pub proof: MerkleProof,
+}
+
+// A node that keeps block headers only. It checks their heights, linkage, seals and the
+// network's checkpoints, follows the heaviest header chain it is shown, and learns about
+// the transactions of the addresses it watches from Merkle proofs against those headers.
+// Hashes of version 0 headers depend on the body, so above genesis they are only taken
+// up to the last checkpoint, and every later header has its hash recomputed. A proof shows a transaction is in the chain, but a peer can withhold
+// proofs, so what is known about an address is as complete as the proofs received.
+#[derive(Debug)]
+pub struct LightClient {
+    pub network_id: String,
+    pub params: ChainParams,
+    headers: Vec<BlockHeader>,
+    engine: Box<dyn Consensus>,
+    watched: BTreeSet<String>,
+    // Proven transactions of watched addresses by id
+    proven: BTreeMap<Hash256, TxProof>,
+}
+
+impl LightClient {
+    // A network with a fixed genesis timestamp starts from its genesis block; on any other
+    // the first valid header chain received is adopted
+    pub fn new(network_id: &str, params: ChainParams, watched: impl IntoIterator<Item = String>) -> Self {
+        let headers = params
+            .genesis_timestamp
+            .map(|timestamp| vec![Block::genesis(timestamp).header()])
+            .unwrap_or_default();
+
+        LightClient {
+            network_id: network_id.to_string(),
+            engine: consensus::engine(&params),
+            params,
+            headers,
+            watched: watched.into_iter().collect(),
+            proven: BTreeMap::new(),
+        }
+    }
+
+    pub fn tip(&self) -> Option<&BlockHeader> {
+        self.headers.last()
+    }
+
+    pub fn headers(&self) -> &[BlockHeader] {
+        &self.headers
+    }
+
+    pub fn watched(&self) -> impl Iterator<Item = &String> {
+        self.watched.iter()
+    }
+
+    // Height to ask peers for headers from
+    pub fn sync_height(&self) -> u64 {
+        (self.headers.len() as u64).saturating_sub(SYNC_OVERLAP)
+    }
+
+    // Fork choice over a run of headers from a peer, which may start anywhere up to just
+    // above the tip. Returns whether the headers were adopted.
+    pub fn add_headers(&mut self, headers: Vec<BlockHeader>) -> Result<bool, ValidationError> {
+        let from = match headers.first() {
+            Some(header) => header.index,
+            None => return Ok(false),
+        };
+        if from > self.headers.len() as u64 {
+            return Err(ValidationError::HeightOutOfSequence { height: from });
+        }
+
+        let mut candidate = self.headers[..from as usize].to_vec();
+        candidate.extend(headers);
+        self.check_headers(&candidate, from as usize)?;
+
+        if !self.headers.is_empty() && self.chain_weight(&candidate) <= self.chain_weight(&self.headers) {
+            return Ok(false);
+        }
+
+        self.headers = candidate;
+        // Transactions in blocks that left the chain are no longer confirmed
+        let headers = &self.headers;
+        self.proven
+            .retain(|_, proof| headers.get(proof.height as usize).map(|header| header.hash) == Some(proof.block_hash));
+        Ok(true)
+    }
+
+    // Check `headers` from `from` up, those below being ones already on our chain
+    fn check_headers(&self, headers: &[BlockHeader], from: usize) -> Result<(), ValidationError> {
+        if from == 0 {
+            let genesis = Block::from_header(headers[0].clone());
+            if genesis.index != 0 || genesis.hash != genesis.calculate_hash() {
+                return Err(ValidationError::InvalidGenesis);
+            }
+            if self.headers.first().is_some_and(|ours| ours.hash != genesis.hash) {
+                return Err(ValidationError::GenesisMismatch);
+            }
+        }
+
+        for header in &headers[from..] {
+            if let Some(checkpoint) = self.params.checkpoints.get(&header.index) {
+                if *checkpoint != header.hash {
+                    return Err(ValidationError::CheckpointConflict {
+                        height: header.index,
+                        expected: *checkpoint,
+                        found: header.hash,
+                    });
+                }
+            }
+        }
+
+        // Nothing but a checkpoint vouches for a version 0 header's claimed hash
+        let legacy_height = self.params.last_checkpoint().unwrap_or(0);
+        for i in from.max(1)..headers.len() {
+            if headers[i].version == 0 && headers[i].index > legacy_height {
+                return Err(ValidationError::UnverifiableHeader { height: headers[i].index });
+            }
+            let block = Block::from_header(headers[i].clone());
+            let parent = Block::from_header(headers[i - 1].clone());
+            check_header(self.engine.as_ref(), &block, &parent, None, false, true)?;
+        }
+
+        Ok(())
+    }
+
+    fn chain_weight(&self, headers: &[BlockHeader]) -> u128 {
+        headers
+            .iter()
+            .skip(1)
+            .map(|header| self.engine.block_weight(&Block::from_header(header.clone())))
+            .sum()
+    }
+
+    // Record a proven transaction of a watched address, returning whether it was new.
+    // Proofs of other addresses' transactions are ignored.
+    pub fn add_proof(&mut self, proof: TxProof) -> Result<bool, ValidationError> {
+        let transaction = &proof.transaction;
+        if !self.watched.contains(&transaction.sender) && !self.watched.contains(&transaction.receiver) {
+            return Ok(false);
+        }
+
+        let header = self
+            .headers
+            .get(proof.height as usize)
+            .filter(|header| header.hash == proof.block_hash)
+            .ok_or(ValidationError::UnknownBlock { hash: proof.block_hash })?;
+        let txid = transaction.id();
+        if header.version == 0 || !proof.proof.verify(&txid, &header.tx_root) {
+            return Err(ValidationError::InvalidProof { txid });
+        }
+
+        Ok(self.proven.insert(txid, proof).is_none())
+    }
+
+    // Proven transactions of watched addresses, oldest first
+    pub fn proven_transactions(&self) -> Vec<&TxProof> {
+        let mut proofs: Vec<&TxProof> = self.proven.values().collect();
+        proofs.sort_by_key(|proof| (proof.height, proof.proof.index));
+        proofs
+    }
+
+    // Blocks on top of a proven transaction, counting its own
+    pub fn confirmations(&self, proof: &TxProof) -> u64 {
+        self.tip().map_or(0, |tip| (tip.index + 1).saturating_sub(proof.height))
+    }
+
+    // Balance of a watched address as far as the proofs received show
+    pub fn balance(&self, address: &str) -> f32 {
+        self.proven.values().map(|proof| proof.transaction.balance_delta(address)).sum()
+    }
+}
@@ -1,25 +1,382 @@
-mod blockchain;
-mod p2p;
-mod algo;
-
-use blockchain::{Blockchain, Transaction};
-use clap::{App, Arg, SubCommand};
+use blockchain::blockchain::{AddressHistory, ChainStats, ChainTip, Direction};
+use blockchain::consensus::{self, DoubleSignEvidence};
+use blockchain::error::{ConfigError, StorageError};
+use blockchain::handle::Balance;
+use blockchain::notify::DEFAULT_MAX_HOOKS;
+use blockchain::{
+    start_light_node, start_p2p_node, Block, BlockHeader, Blockchain, BlockchainHandle, ChainFileFormat, ChainParams, ConsensusConfig, Error,
+    Hash256, LightClient, Notifier, NotifyConfig, PowAlgorithm, Transaction, TransactionStatus, TxKind, DEFAULT_NETWORK_ID, TESTNET_NETWORK_ID,
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use log::{error, info};
-use p2p::start_p2p_node;
-use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc;
+use std::fmt::Display;
+use std::str::FromStr;
+
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+const DEFAULT_BLOCKS_LIMIT: usize = 10;
+
+// A block named by height or by hash
+enum BlockRef {
+    Height(u64),
+    Hash(Hash256),
+}
+
+impl FromStr for BlockRef {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // A height first, since "0" would also parse as the zero hash
+        if let Ok(height) = s.parse::<u64>() {
+            return Ok(BlockRef::Height(height));
+        }
+        s.parse::<Hash256>()
+            .map(BlockRef::Hash)
+            .map_err(|_| format!("'{}' is neither a height nor a 64-digit hex hash", s))
+    }
+}
+
+impl std::fmt::Display for BlockRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlockRef::Height(height) => write!(f, "Block at height {}", height),
+            BlockRef::Hash(hash) => write!(f, "Block {}", hash),
+        }
+    }
+}
+
+// ~/.rustchain, or ./.rustchain when there is no home directory
+fn default_datadir() -> String {
+    let home = std::env::var_os("HOME").map(std::path::PathBuf::from).unwrap_or_default();
+    home.join(".rustchain").to_string_lossy().into_owned()
+}
+
+// Process exit status for each kind of failure, so scripts can tell them apart
+fn exit_code(error: &Error) -> i32 {
+    match error {
+        Error::NotFound(_) => 1,
+        Error::Config(_) => 2,
+        Error::Validation(_) | Error::Storage(StorageError::InvalidChain { .. }) => 3,
+        Error::Storage(_) => 4,
+        Error::Network(_) => 5,
+        Error::Consensus(_) => 6,
+    }
+}
+
+// Print the outcome of an interactive command; failures are reported and the session goes on
+fn print_outcome(outcome: blockchain::Result<String>) {
+    match outcome {
+        Ok(msg) => println!("{}", msg),
+        Err(err) => println!("Error: {}", err),
+    }
+}
+
+// Parse a command line value, reporting which option was malformed
+fn parse_arg<T>(matches: &ArgMatches, name: &str, what: &'static str) -> Result<T, ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = matches.value_of(name).unwrap_or_default();
+    value.parse().map_err(|e| ConfigError::invalid(what, value, e))
+}
+
+fn describe_transaction(id: &Hash256, status: &TransactionStatus) -> String {
+    match status {
+        TransactionStatus::Confirmed { transaction, location, confirmations } => format!(
+            "Transaction {}: {} -> {} ({})\n  Confirmed in block {} (height {}, position {}) with {} confirmation(s)",
+            id,
+            transaction.sender,
+            transaction.receiver,
+            transaction.amount,
+            location.block_hash,
+            location.height,
+            location.position,
+            confirmations,
+        ),
+        TransactionStatus::Pending(transaction) => format!(
+            "Transaction {}: {} -> {} ({})\n  Pending",
+            id, transaction.sender, transaction.receiver, transaction.amount,
+        ),
+    }
+}
+
+// One line per transaction, as listed in blocks and the mempool
+fn describe_transaction_line(transaction: &Transaction) -> String {
+    let kind = match transaction.kind {
+        TxKind::Transfer => "",
+        TxKind::Stake => " [stake]",
+        TxKind::Evidence(_) => " [evidence]",
+    };
+    format!(
+        "    {}: {} -> {} ({}){}",
+        transaction.id(), transaction.sender, transaction.receiver, transaction.amount, kind,
+    )
+}
+
+// The tip is looked up separately and may have moved below `block` since
+fn describe_block(block: &Block, tip: &ChainTip) -> String {
+    let mut lines = vec![
+        format!("Block {}", block.hash),
+        format!("  Height: {} ({} confirmation(s))", block.index, (tip.height + 1).saturating_sub(block.index)),
+        format!("  Timestamp: {}", block.timestamp),
+        format!("  Previous: {}", block.previous_hash),
+        format!("  Nonce: {}", block.nonce),
+        format!("  Version: {}", block.version),
+    ];
+    if block.version >= 1 {
+        lines.push(format!("  Transactions root: {}", block.tx_root));
+    }
+    if let Some(signature) = &block.signature {
+        lines.push(format!("  Signed by: {}", signature.signer));
+    }
+    
+    if block.index < tip.pruned_height {
+        lines.push("  Transactions: pruned".to_string());
+    } else {
+        lines.push(format!("  Transactions: {}", block.transactions.len()));
+        lines.extend(block.transactions.iter().map(describe_transaction_line));
+    }
+    
+    lines.join("\n")
+}
+
+fn describe_stats(stats: &ChainStats) -> String {
+    let or_none = |value: Option<String>| value.unwrap_or_else(|| "n/a".to_string());
+    [
+        format!("Blocks: {}", stats.blocks),
+        format!("Average block time: {}", or_none(stats.average_block_time.map(|seconds| format!("{:.1}s", seconds)))),
+        format!("Transactions: {}", stats.transactions),
+        format!("Transactions per block: {}", or_none(stats.transactions_per_block.map(|mean| format!("{:.2}", mean)))),
+        format!("Pending transactions: {}", stats.pending_transactions),
+        format!("Difficulty: {}", or_none(stats.difficulty.map(|difficulty| difficulty.to_string()))),
+    ]
+    .join("\n")
+}
+
+fn describe_history(address: &str, offset: usize, history: &AddressHistory) -> String {
+    let mut lines = vec![format!(
+        "History for {} (showing {} of {} transactions from offset {})",
+        address,
+        history.entries.len(),
+        history.total,
+        offset,
+    )];
+    
+    for entry in &history.entries {
+        let (direction, sign) = match entry.direction {
+            Direction::Sent => ("sent to", "-"),
+            Direction::Received => ("received from", "+"),
+            Direction::SelfTransfer => ("self transfer", " "),
+            Direction::Staked => ("staked on", "-"),
+            // Stake placed on a validator stays its owner's, so the balance does not move
+            Direction::StakeReceived => ("stake from", " "),
+            Direction::Reported => ("reported", " "),
+            Direction::Slashed => ("slashed on report by", " "),
+        };
+        lines.push(format!(
+            "  #{} @{} {} {} {}{} -> balance {} [{}]",
+            entry.height,
+            entry.timestamp,
+            direction,
+            entry.counterparty,
+            sign,
+            entry.amount,
+            entry.balance,
+            entry.txid,
+        ));
+    }
+    
+    lines.join("\n")
+}
+
+// Build a staking transaction, carried to the chain task like any other transaction
+fn stake_transaction(owner: &str, validator: &str, amount: f32) -> Transaction {
+    let mut transaction = Transaction::new(owner.to_string(), validator.to_string(), amount);
+    transaction.kind = TxKind::Stake;
+    transaction
+}
+
+// Read two conflicting block headers (or full blocks) from JSON files as double-sign evidence
+fn evidence_transaction(reporter: &str, first: &str, second: &str) -> blockchain::Result<Transaction> {
+    let read_header = |path: &str| -> blockchain::Result<BlockHeader> {
+        let json = std::fs::read_to_string(path).map_err(StorageError::Io)?;
+        Ok(serde_json::from_str(&json).map_err(|e| ConfigError::invalid("block header file", path, e))?)
+    };
+    
+    let evidence = DoubleSignEvidence {
+        first: read_header(first)?,
+        second: read_header(second)?,
+    };
+    let offender = evidence.offender().unwrap_or_default().to_string();
+    let mut transaction = Transaction::new(reporter.to_string(), offender, 0.0);
+    transaction.kind = TxKind::Evidence(Box::new(evidence));
+    Ok(transaction)
+}
+
+// Requests shared by the command line and interactive mode, each describing its outcome
+
+async fn submit(handle: &BlockchainHandle, transaction: Transaction) -> blockchain::Result<String> {
+    let id = handle.add_transaction(transaction).await?;
+    Ok(format!("Transaction {} added to pending pool", id))
+}
+
+async fn mine(handle: &BlockchainHandle, address: &str) -> blockchain::Result<String> {
+    handle.mine(address).await?;
+    Ok("Block mined successfully".to_string())
+}
+
+async fn balance(handle: &BlockchainHandle, address: &str) -> String {
+    let Balance { balance, staked } = handle.balance(address).await;
+    if staked > 0.0 {
+        format!("Balance for {}: {} (staked: {})", address, balance, staked)
+    } else {
+        format!("Balance for {}: {}", address, balance)
+    }
+}
+
+async fn lookup_transaction(handle: &BlockchainHandle, id: Hash256) -> blockchain::Result<String> {
+    match handle.transaction(id).await {
+        Some(status) => Ok(describe_transaction(&id, &status)),
+        None => Err(Error::NotFound(format!("Transaction {}", id))),
+    }
+}
+
+async fn history(handle: &BlockchainHandle, address: &str, offset: usize, limit: usize) -> String {
+    describe_history(address, offset, &handle.history(address, offset, limit).await)
+}
+
+async fn block(handle: &BlockchainHandle, block_ref: BlockRef) -> blockchain::Result<String> {
+    let block = match block_ref {
+        BlockRef::Height(height) => handle.block_by_height(height).await,
+        BlockRef::Hash(hash) => handle.block_by_hash(hash).await,
+    };
+    let block = block.ok_or_else(|| Error::NotFound(block_ref.to_string()))?;
+    
+    Ok(describe_block(&block, &handle.tip().await))
+}
+
+async fn blocks(handle: &BlockchainHandle, from: u64, limit: usize) -> String {
+    let blocks = handle.blocks(from, limit).await;
+    let tip = handle.tip().await;
+    
+    let mut lines = vec![match (blocks.first(), blocks.last()) {
+        (Some(first), Some(last)) => format!("Blocks {} to {} of {}", first.index, last.index, tip.height + 1),
+        _ => format!("No blocks from height {} (tip is at height {})", from, tip.height),
+    }];
+    for block in &blocks {
+        let transactions = if block.index < tip.pruned_height {
+            "pruned".to_string()
+        } else {
+            format!("{} tx", block.transactions.len())
+        };
+        lines.push(format!("  #{} {} @{} ({})", block.index, block.hash, block.timestamp, transactions));
+    }
+    
+    lines.join("\n")
+}
+
+async fn tip(handle: &BlockchainHandle) -> String {
+    let tip = handle.tip().await;
+    let mut lines = vec![
+        format!("Tip: block {} at height {}", tip.hash, tip.height),
+        format!("  Timestamp: {}", tip.timestamp),
+        format!("  Chain weight: {}", tip.weight),
+    ];
+    if tip.pruned_height > 0 {
+        lines.push(format!("  Bodies kept from height {}", tip.pruned_height));
+    }
+    
+    lines.join("\n")
+}
+
+async fn pending(handle: &BlockchainHandle) -> String {
+    let transactions = handle.pending_transactions().await;
+    let mut lines = vec![format!("{} pending transaction(s)", transactions.len())];
+    lines.extend(transactions.iter().map(describe_transaction_line));
+    
+    lines.join("\n")
+}
+
+async fn stats(handle: &BlockchainHandle) -> String {
+    describe_stats(&handle.stats().await)
+}
+
+async fn validate(handle: &BlockchainHandle) -> String {
+    match handle.validate().await {
+        Ok(()) => "Blockchain validation: Valid".to_string(),
+        Err(e) => format!("Blockchain validation: Invalid ({})", e),
+    }
+}
+
+async fn save(handle: &BlockchainHandle, path: &str, format: ChainFileFormat, verify: bool) -> blockchain::Result<String> {
+    handle.save(path, format, verify).await?;
+    if verify {
+        Ok(format!("Blockchain saved to {} ({}) and verified", path, format))
+    } else {
+        Ok(format!("Blockchain saved to {} ({})", path, format))
+    }
+}
+
+async fn load(handle: &BlockchainHandle, path: &str, verify: bool) -> blockchain::Result<String> {
+    if verify {
+        let summary = handle.check_file(path).await?;
+        return Ok(format!(
+            "{} is valid: network {}, {} blocks, tip {}",
+            path, summary.network_id, summary.blocks, summary.tip,
+        ));
+    }
+    
+    handle.load(path).await?;
+    Ok(format!("Blockchain loaded from {}", path))
+}
+
+async fn snapshot(handle: &BlockchainHandle, path: &str, height: Option<u64>) -> blockchain::Result<String> {
+    let snapshot = handle.export_snapshot(path, height).await?;
+    Ok(format!(
+        "Snapshot at height {} (block {}) saved to {}",
+        snapshot.height, snapshot.block_hash, path,
+    ))
+}
 
-#[derive(Debug)]
-enum BlockchainResponse {
-    Success(String),
-    Error(String),
+async fn bootstrap(handle: &BlockchainHandle, path: &str, trusted_hash: Hash256) -> blockchain::Result<String> {
+    let height = handle.bootstrap(path, trusted_hash).await?;
+    Ok(format!("Bootstrapped from snapshot at height {}", height))
 }
 
-async fn run_interactive_mode(blockchain: Arc<Mutex<Blockchain>>, tx: mpsc::Sender<BlockchainCommand>) {
+async fn prune(handle: &BlockchainHandle, depth: u64) -> blockchain::Result<String> {
+    let outcome = handle.prune(depth).await?;
+    Ok(format!(
+        "Pruned {} block bodies, keeping bodies from height {}",
+        outcome.pruned, outcome.pruned_height,
+    ))
+}
+
+async fn run_interactive_mode(handle: BlockchainHandle) {
     use std::io::{self, BufRead, Write};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use tokio::sync::broadcast::error::RecvError;
     
     println!("Interactive mode started. Type 'help' for commands.");
     
+    // Chain, mempool and peer events are printed as they happen once turned on with `events on`
+    let show_events = Arc::new(AtomicBool::new(false));
+    let mut events = handle.subscribe();
+    let printing = show_events.clone();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) if printing.load(Ordering::Relaxed) => println!("[event] {}", event),
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) if printing.load(Ordering::Relaxed) => {
+                    println!("[event] {} events missed", missed)
+                }
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+    
     let stdin = io::stdin();
     let mut stdout = io::stdout();
     
@@ -39,50 +396,55 @@ async fn run_interactive_mode(blockchain: Arc<Mutex<Blockchain>>, tx: mpsc::Send
         match parts[0] {
             "help" => {
                 println!("Available commands:");
-                println!("  save <path>            - Save blockchain to disk");
-                println!("  load <path>            - Load blockchain from disk");
+                println!("  save <path> [json|binary] [--verify] - Save blockchain to disk");
+                println!("  load <path> [--verify] - Load blockchain from disk, or only check it with --verify");
                 println!("  transaction <from> <to> <amount> - Create transaction");
+                println!("  stake <from> <amount> <validator> - Stake funds on a validator (proof of stake)");
+                println!("  report <reporter> <header-a.json> <header-b.json> - Submit double-sign evidence");
                 println!("  mine <address>         - Mine pending transactions");
                 println!("  balance <address>      - Check balance");
+                println!("  tx <id>                - Look up a transaction by id");
+                println!("  history <address> [offset] [limit] - Show transactions for an address");
+                println!("  block <height|hash>    - Show a block");
+                println!("  blocks <from> [limit]  - List blocks from a height");
+                println!("  tip                    - Show the chain tip");
+                println!("  pending                - List pending transactions");
+                println!("  stats                  - Show chain statistics");
                 println!("  validate               - Validate blockchain");
+                println!("  snapshot <path> [height] - Export a state snapshot");
+                println!("  bootstrap <path> <hash> - Replace the chain with a trusted snapshot");
+                println!("  prune <depth>          - Drop block bodies more than <depth> blocks below the tip");
+                println!("  events <on|off>        - Print chain, mempool and peer events as they happen");
                 println!("  exit                   - Exit interactive mode");
                 println!("  help                   - Show this help message");
             },
             "save" => {
                 if parts.len() < 2 {
-                    println!("Usage: save <path>");
+                    println!("Usage: save <path> [json|binary] [--verify]");
                     continue;
                 }
+                let verify = parts[2..].contains(&"--verify");
+                let format = match parts[2..].iter().find(|part| **part != "--verify") {
+                    Some(format) => match format.parse::<ChainFileFormat>() {
+                        Ok(format) => format,
+                        Err(e) => {
+                            println!("{}", e);
+                            continue;
+                        }
+                    },
+                    None => ChainFileFormat::Json,
+                };
                 
-                // Create response channel
-                let (resp_tx, mut resp_rx) = mpsc::channel::<BlockchainResponse>(1);
-                
-                let _ = tx.send(BlockchainCommand::SaveChain(parts[1].to_string(), resp_tx)).await;
-                
-                // Wait for response
-                if let Some(response) = resp_rx.recv().await {
-                    match response {
-                        BlockchainResponse::Success(msg) => println!("{}", msg),
-                        BlockchainResponse::Error(err) => println!("Error: {}", err),
-                    }
-                }
+                print_outcome(save(&handle, parts[1], format, verify).await);
             },
             "load" => {
                 if parts.len() < 2 {
-                    println!("Usage: load <path>");
+                    println!("Usage: load <path> [--verify]");
                     continue;
                 }
+                let verify = parts[2..].contains(&"--verify");
                 
-                let (resp_tx, mut resp_rx) = mpsc::channel::<BlockchainResponse>(1);
-                let _ = tx.send(BlockchainCommand::LoadChain(parts[1].to_string(), resp_tx)).await;
-                
-                // Wait for response
-                if let Some(response) = resp_rx.recv().await {
-                    match response {
-                        BlockchainResponse::Success(msg) => println!("{}", msg),
-                        BlockchainResponse::Error(err) => println!("Error: {}", err),
-                    }
-                }
+                print_outcome(load(&handle, parts[1], verify).await);
             },
             "transaction" => {
                 if parts.len() < 4 {
@@ -97,22 +459,41 @@ async fn run_interactive_mode(blockchain: Arc<Mutex<Blockchain>>, tx: mpsc::Send
                     }
                 };
                 
-                let transaction = Transaction {
-                    sender: parts[1].to_string(),
-                    receiver: parts[2].to_string(),
-                    amount,
+                let transaction = Transaction::new(parts[1].to_string(), parts[2].to_string(), amount);
+                
+                print_outcome(submit(&handle, transaction).await);
+            },
+            "stake" => {
+                if parts.len() < 4 {
+                    println!("Usage: stake <from> <amount> <validator>");
+                    continue;
+                }
+                let amount = match parts[2].parse::<f32>() {
+                    Ok(val) => val,
+                    Err(_) => {
+                        println!("Invalid amount");
+                        continue;
+                    }
                 };
                 
-                let (resp_tx, mut resp_rx) = mpsc::channel::<BlockchainResponse>(1);
-                let _ = tx.send(BlockchainCommand::AddTransaction(transaction, resp_tx)).await;
+                let transaction = stake_transaction(parts[1], parts[3], amount);
                 
-                // Wait for response
-                if let Some(response) = resp_rx.recv().await {
-                    match response {
-                        BlockchainResponse::Success(msg) => println!("{}", msg),
-                        BlockchainResponse::Error(err) => println!("Error: {}", err),
-                    }
+                print_outcome(submit(&handle, transaction).await);
+            },
+            "report" => {
+                if parts.len() < 4 {
+                    println!("Usage: report <reporter> <header-a.json> <header-b.json>");
+                    continue;
                 }
+                let transaction = match evidence_transaction(parts[1], parts[2], parts[3]) {
+                    Ok(transaction) => transaction,
+                    Err(e) => {
+                        println!("Error: {}", e);
+                        continue;
+                    }
+                };
+                
+                print_outcome(submit(&handle, transaction).await);
             },
             "mine" => {
                 if parts.len() < 2 {
@@ -120,16 +501,7 @@ async fn run_interactive_mode(blockchain: Arc<Mutex<Blockchain>>, tx: mpsc::Send
                     continue;
                 }
                 
-                let (resp_tx, mut resp_rx) = mpsc::channel::<BlockchainResponse>(1);
-                let _ = tx.send(BlockchainCommand::MineBlock(parts[1].to_string(), resp_tx)).await;
-                
-                // Wait for response
-                if let Some(response) = resp_rx.recv().await {
-                    match response {
-                        BlockchainResponse::Success(msg) => println!("{}", msg),
-                        BlockchainResponse::Error(err) => println!("Error: {}", err),
-                    }
-                }
+                print_outcome(mine(&handle, parts[1]).await);
             },
             "balance" => {
                 if parts.len() < 2 {
@@ -137,27 +509,137 @@ async fn run_interactive_mode(blockchain: Arc<Mutex<Blockchain>>, tx: mpsc::Send
                     continue;
                 }
                 
-                let (resp_tx, mut resp_rx) = mpsc::channel::<BlockchainResponse>(1);
-                let _ = tx.send(BlockchainCommand::GetBalance(parts[1].to_string(), resp_tx)).await;
+                println!("{}", balance(&handle, parts[1]).await);
+            },
+            "tx" => {
+                if parts.len() < 2 {
+                    println!("Usage: tx <id>");
+                    continue;
+                }
+                let id = match parts[1].parse::<Hash256>() {
+                    Ok(id) => id,
+                    Err(e) => {
+                        println!("Invalid transaction id: {}", e);
+                        continue;
+                    }
+                };
                 
-                // Wait for response
-                if let Some(response) = resp_rx.recv().await {
-                    match response {
-                        BlockchainResponse::Success(msg) => println!("{}", msg),
-                        BlockchainResponse::Error(err) => println!("Error: {}", err),
+                print_outcome(lookup_transaction(&handle, id).await);
+            },
+            "history" => {
+                if parts.len() < 2 {
+                    println!("Usage: history <address> [offset] [limit]");
+                    continue;
+                }
+                let offset = match parts.get(2).map(|s| s.parse::<usize>()).unwrap_or(Ok(0)) {
+                    Ok(val) => val,
+                    Err(_) => {
+                        println!("Invalid offset");
+                        continue;
+                    }
+                };
+                let limit = match parts.get(3).map(|s| s.parse::<usize>()).unwrap_or(Ok(DEFAULT_HISTORY_LIMIT)) {
+                    Ok(val) => val,
+                    Err(_) => {
+                        println!("Invalid limit");
+                        continue;
+                    }
+                };
+                
+                println!("{}", history(&handle, parts[1], offset, limit).await);
+            },
+            "block" => {
+                let block_ref = match parts.get(1).map(|s| s.parse::<BlockRef>()) {
+                    Some(Ok(block_ref)) => block_ref,
+                    Some(Err(e)) => {
+                        println!("Invalid block: {}", e);
+                        continue;
+                    }
+                    None => {
+                        println!("Usage: block <height|hash>");
+                        continue;
+                    }
+                };
+                
+                print_outcome(block(&handle, block_ref).await);
+            },
+            "blocks" => {
+                let from = match parts.get(1).map(|s| s.parse::<u64>()) {
+                    Some(Ok(val)) => val,
+                    _ => {
+                        println!("Usage: blocks <from> [limit]");
+                        continue;
+                    }
+                };
+                let limit = match parts.get(2).map(|s| s.parse::<usize>()).unwrap_or(Ok(DEFAULT_BLOCKS_LIMIT)) {
+                    Ok(val) => val,
+                    Err(_) => {
+                        println!("Invalid limit");
+                        continue;
                     }
+                };
+                
+                println!("{}", blocks(&handle, from, limit).await);
+            },
+            "tip" => {
+                println!("{}", tip(&handle).await);
+            },
+            "pending" => {
+                println!("{}", pending(&handle).await);
+            },
+            "stats" => {
+                println!("{}", stats(&handle).await);
+            },
+            "snapshot" => {
+                if parts.len() < 2 {
+                    println!("Usage: snapshot <path> [height]");
+                    continue;
                 }
+                let height = match parts.get(2).map(|s| s.parse::<u64>()) {
+                    Some(Ok(val)) => Some(val),
+                    Some(Err(_)) => {
+                        println!("Invalid height");
+                        continue;
+                    }
+                    None => None,
+                };
+                
+                print_outcome(snapshot(&handle, parts[1], height).await);
             },
-            "validate" => {
-                let (resp_tx, mut resp_rx) = mpsc::channel::<BlockchainResponse>(1);
-                let _ = tx.send(BlockchainCommand::ValidateChain(resp_tx)).await;
+            "bootstrap" => {
+                if parts.len() < 3 {
+                    println!("Usage: bootstrap <path> <hash>");
+                    continue;
+                }
+                let hash = match parts[2].parse::<Hash256>() {
+                    Ok(hash) => hash,
+                    Err(e) => {
+                        println!("Invalid block hash: {}", e);
+                        continue;
+                    }
+                };
                 
-                // Wait for response
-                if let Some(response) = resp_rx.recv().await {
-                    match response {
-                        BlockchainResponse::Success(msg) => println!("{}", msg),
-                        BlockchainResponse::Error(err) => println!("Error: {}", err),
+                print_outcome(bootstrap(&handle, parts[1], hash).await);
+            },
+            "prune" => {
+                let depth = match parts.get(1).map(|s| s.parse::<u64>()) {
+                    Some(Ok(val)) => val,
+                    _ => {
+                        println!("Usage: prune <depth>");
+                        continue;
                     }
+                };
+                
+                print_outcome(prune(&handle, depth).await);
+            },
+            "validate" => {
+                println!("{}", validate(&handle).await);
+            },
+            "events" => {
+                match parts.get(1) {
+                    Some(&"on") => show_events.store(true, Ordering::Relaxed),
+                    Some(&"off") => show_events.store(false, Ordering::Relaxed),
+                    _ => println!("Usage: events <on|off>"),
                 }
             },
             "exit" => {
@@ -172,11 +654,19 @@ async fn run_interactive_mode(blockchain: Arc<Mutex<Blockchain>>, tx: mpsc::Send
 }
 
 #[tokio::main]
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("Error: {}", e);
+        std::process::exit(exit_code(&e));
+    }
+}
 
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn run() -> blockchain::Result<()> {
     env_logger::init();
     
     // Parse command line arguments
+    let default_datadir = default_datadir();
+    let default_max_hooks = DEFAULT_MAX_HOOKS.to_string();
     let matches = App::new("RustChain")
         .version("0.1.0")
         .author("Your Name")
@@ -207,6 +697,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .takes_value(true)
                 .default_value("4"),
         )
+        .arg(
+            Arg::with_name("pow_hash")
+                .long("pow-hash")
+                .value_name("HASH")
+                .help("Sets the hash proof of work is measured in; block ids stay SHA-256")
+                .possible_values(&["sha256", "double-sha256", "blake3", "scrypt"])
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("reward")
                 .short("r")
@@ -216,6 +714,121 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .takes_value(true)
                 .default_value("100.0"),
         )
+        .arg(
+            Arg::with_name("network")
+                .short("n")
+                .long("network")
+                .value_name("NETWORK_ID")
+                .help("Sets the network id recorded in saved chains (rustchain-testnet selects the shared test network)")
+                .takes_value(true)
+                .default_value(DEFAULT_NETWORK_ID),
+        )
+        .arg(
+            Arg::with_name("checkpoint")
+                .long("checkpoint")
+                .value_name("HEIGHT:HASH")
+                .help("Pins a known-good block hash at a height; chains conflicting with it are rejected")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("authority")
+                .long("authority")
+                .value_name("PUBKEY")
+                .help("Runs proof of authority with this signer (hex ed25519 public key); repeat in signing order")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("genesis_validator")
+                .long("genesis-validator")
+                .value_name("PUBKEY")
+                .help("Runs proof of stake with this validator (hex ed25519 public key) proposing until stake is locked")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with("authority"),
+        )
+        .arg(
+            Arg::with_name("signer_key")
+                .long("signer-key")
+                .value_name("FILE")
+                .help("Key file this node signs blocks with under proof of authority or stake (created if missing)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("datadir")
+                .long("datadir")
+                .value_name("DIR")
+                .help("Sets the directory the chain and mempool are loaded from and persisted to")
+                .takes_value(true)
+                .default_value(&default_datadir),
+        )
+        .arg(
+            Arg::with_name("prune")
+                .long("prune")
+                .value_name("DEPTH")
+                .help("Drops block bodies more than DEPTH blocks below the tip, keeping headers")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mining_threads")
+                .long("mining-threads")
+                .value_name("THREADS")
+                .help("Sets how many threads mine proof-of-work blocks (defaults to one per CPU)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("blocknotify")
+                .long("blocknotify")
+                .value_name("COMMAND")
+                .help("Runs COMMAND through the shell for each new block (%s is replaced by the block hash, %h by its height)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("walletnotify")
+                .long("walletnotify")
+                .value_name("COMMAND")
+                .help("Runs COMMAND through the shell when a transfer to a watched address is received and again when it is confirmed (%s txid, %a address, %b block hash or \"unconfirmed\", %h height or -1)")
+                .takes_value(true)
+                .requires("notify_address"),
+        )
+        .arg(
+            Arg::with_name("notify_address")
+                .long("notify-address")
+                .value_name("ADDRESS")
+                .help("Watches ADDRESS for --walletnotify")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("notify_hooks")
+                .long("notify-hooks")
+                .value_name("COUNT")
+                .help("Sets how many notify commands may run at once")
+                .takes_value(true)
+                .default_value(&default_max_hooks),
+        )
+        .arg(
+            Arg::with_name("light")
+                .long("light")
+                .help("Runs a light node that syncs only block headers and proofs of the watched addresses' transactions")
+                .takes_value(false)
+                .conflicts_with("interactive"),
+        )
+        .arg(
+            Arg::with_name("watch")
+                .long("watch")
+                .value_name("ADDRESS")
+                .help("Watches ADDRESS on a light node, checking proofs of the transactions touching it")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("light"),
+        )
         .arg(
             Arg::with_name("interactive")
                 .short("i")
@@ -251,6 +864,44 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .required(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("stake")
+                .about("Stake funds on a validator under proof of stake")
+                .arg(
+                    Arg::with_name("owner")
+                        .help("Address the stake is paid from")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("amount")
+                        .help("Amount to stake")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("validator")
+                        .help("Validator public key to stake on")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("report")
+                .about("Submit evidence that a validator signed two blocks at one height")
+                .arg(
+                    Arg::with_name("reporter")
+                        .help("Address submitting the evidence")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("first")
+                        .help("JSON file with the first block or block header")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("second")
+                        .help("JSON file with the conflicting block or block header")
+                        .required(true),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("balance")
                 .about("Check balance for an address")
@@ -260,6 +911,78 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .required(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("tx")
+                .about("Look up a transaction by id")
+                .arg(
+                    Arg::with_name("id")
+                        .help("Transaction id")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("history")
+                .about("Show the transactions touching an address")
+                .arg(
+                    Arg::with_name("address")
+                        .help("Address to inspect")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("offset")
+                        .long("offset")
+                        .value_name("OFFSET")
+                        .help("Number of transactions to skip")
+                        .takes_value(true)
+                        .default_value("0"),
+                )
+                .arg(
+                    Arg::with_name("limit")
+                        .long("limit")
+                        .value_name("LIMIT")
+                        .help("Maximum number of transactions to show")
+                        .takes_value(true)
+                        .default_value("20"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("block")
+                .about("Show a block by height or hash")
+                .arg(
+                    Arg::with_name("block")
+                        .help("Block height or hash")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("blocks")
+                .about("List consecutive blocks from a height")
+                .arg(
+                    Arg::with_name("from")
+                        .help("Height of the first block")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("limit")
+                        .long("limit")
+                        .value_name("LIMIT")
+                        .help("Maximum number of blocks to show")
+                        .takes_value(true)
+                        .default_value("10"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("tip")
+                .about("Show the chain tip"),
+        )
+        .subcommand(
+            SubCommand::with_name("pending")
+                .about("List pending transactions"),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Show chain statistics: average block time, transactions per block and difficulty"),
+        )
         .subcommand(
             SubCommand::with_name("save")
                 .about("Save blockchain to disk")
@@ -267,6 +990,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     Arg::with_name("path")
                         .help("Path to save blockchain")
                         .required(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("File format to write")
+                        .possible_values(&["json", "binary"])
+                        .takes_value(true)
+                        .default_value("json"),
+                )
+                .arg(
+                    Arg::with_name("verify")
+                        .long("verify")
+                        .help("Read the file back after saving and check it matches the chain")
+                        .takes_value(false),
                 ),
         )
         .subcommand(
@@ -274,170 +1013,250 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .about("Load blockchain from disk")
                 .arg(
                     Arg::with_name("path")
-                        .help("Path to load blockchain from")
+                        .help("Path to load blockchain from (JSON or binary, detected automatically)")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("verify")
+                        .long("verify")
+                        .help("Only check the file's checksum and chain without loading it")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("Export balances, nonces and the header chain at a height")
+                .arg(
+                    Arg::with_name("path")
+                        .help("Path to write the snapshot to")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("height")
+                        .long("height")
+                        .value_name("HEIGHT")
+                        .help("Height to snapshot (defaults to the tip)")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bootstrap")
+                .about("Replace the chain with one started from a trusted snapshot")
+                .arg(
+                    Arg::with_name("path")
+                        .help("Path to the snapshot")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("hash")
+                        .help("Trusted hash of the snapshot block")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("keygen")
+                .about("Create a signer key file and print its public key")
+                .arg(
+                    Arg::with_name("path")
+                        .help("Path to write the key to")
                         .required(true),
                 ),
         )
         .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("keygen") {
+        let path = matches.value_of("path").unwrap();
+        if std::path::Path::new(path).exists() {
+            return Err(ConfigError::AlreadyExists(path.to_string()).into());
+        }
+        let keypair = consensus::load_or_create_key(path)?;
+        println!("Public key: {}", consensus::public_key_hex(&keypair));
+        return Ok(());
+    }
+
     // Parse difficulty and mining reward
-    let difficulty = matches
-        .value_of("difficulty")
-        .unwrap()
-        .parse::<usize>()
-        .expect("Difficulty must be a number");
-    
-    let mining_reward = matches
-        .value_of("reward")
-        .unwrap()
-        .parse::<f32>()
-        .expect("Mining reward must be a number");
-
-    // Create a new blockchain
-    let blockchain = Arc::new(Mutex::new(Blockchain::new(difficulty, mining_reward)));
-    
-    // Create channels for communication with the P2P layer
-    let (tx, mut rx) = mpsc::channel::<BlockchainCommand>(100);
-    let tx_clone = tx.clone();
+    let difficulty = parse_arg::<usize>(&matches, "difficulty", "difficulty")?;
+    let mining_reward = parse_arg::<f32>(&matches, "reward", "mining reward")?;
 
-    // Handle blockchain commands in a separate task
-    let blockchain_clone = blockchain.clone();
-    // Replace the existing tokio::spawn block with this:
+    // The shared test network has a fixed chain spec; any other network takes its parameters from the flags
+    let network_id = matches.value_of("network").unwrap();
+    let mut params = if network_id == TESTNET_NETWORK_ID {
+        ChainParams::testnet()
+    } else {
+        ChainParams::new(difficulty, mining_reward)?
+    };
+    if matches.is_present("pow_hash") {
+        params.pow_algorithm = parse_arg::<PowAlgorithm>(&matches, "pow_hash", "proof-of-work hash")?;
+    }
+    let authorities: Vec<String> = matches
+        .values_of("authority")
+        .into_iter()
+        .flatten()
+        .map(|signer| signer.to_string())
+        .collect();
+    if !authorities.is_empty() {
+        params.consensus = ConsensusConfig::ProofOfAuthority { signers: authorities };
+    }
+    let genesis_validators: Vec<String> = matches
+        .values_of("genesis_validator")
+        .into_iter()
+        .flatten()
+        .map(|validator| validator.to_string())
+        .collect();
+    if !genesis_validators.is_empty() {
+        params.consensus = ConsensusConfig::ProofOfStake { genesis_validators };
+    }
+    for checkpoint in matches.values_of("checkpoint").into_iter().flatten() {
+        let invalid = |reason: String| ConfigError::invalid("checkpoint", checkpoint, reason);
+        let (height, hash) = checkpoint
+            .split_once(':')
+            .ok_or_else(|| invalid("expected HEIGHT:HASH".to_string()))?;
+        let height = height.parse::<u64>().map_err(|e| invalid(e.to_string()))?;
+        let hash = hash.parse::<Hash256>().map_err(|e| invalid(e.to_string()))?;
+        params.checkpoints.insert(height, hash);
+    }
 
-    tokio::spawn(async move {
-        while let Some(cmd) = rx.recv().await {
-            match cmd {
-                BlockchainCommand::AddTransaction(tx, resp_tx) => {
-                    let response = {
-                        let mut chain = blockchain_clone.lock().unwrap();
-                        chain.add_transaction(tx.sender, tx.receiver, tx.amount);
-                        BlockchainResponse::Success("Transaction added to pending pool".to_string())
-                    };
-                    let _ = resp_tx.send(response).await;
-                }
-                BlockchainCommand::MineBlock(address, resp_tx) => {
-                    let response = {
-                        let mut chain = blockchain_clone.lock().unwrap();
-                        chain.mine_pending_transactions(&address);
-                        BlockchainResponse::Success("Block mined successfully".to_string())
-                    };
-                    let _ = resp_tx.send(response).await;
-                }
-                BlockchainCommand::GetBalance(address, resp_tx) => {
-                    let response = {
-                        let chain = blockchain_clone.lock().unwrap();
-                        let balance = chain.get_balance(&address);
-                        BlockchainResponse::Success(format!("Balance for {}: {}", address, balance))
-                    };
-                    let _ = resp_tx.send(response).await;
-                }
-                BlockchainCommand::ValidateChain(resp_tx) => {
-                    let response = {
-                        let chain = blockchain_clone.lock().unwrap();
-                        let valid = chain.is_chain_valid();
-                        let status = if valid { "Valid" } else { "Invalid" };
-                        BlockchainResponse::Success(format!("Blockchain validation: {}", status))
-                    };
-                    let _ = resp_tx.send(response).await;
-                }
-                BlockchainCommand::SaveChain(path, resp_tx) => {
-                    let response = {
-                        let chain = blockchain_clone.lock().unwrap();
-                        match chain.save_to_disk(&path) {
-                            Ok(_) => BlockchainResponse::Success(format!("Blockchain saved to {}", path)),
-                            Err(e) => BlockchainResponse::Error(format!("Failed to save blockchain: {}", e)),
-                        }
-                    };
-                    let _ = resp_tx.send(response).await;
-                }
-                BlockchainCommand::LoadChain(path, resp_tx) => {
-                    let (difficulty, mining_reward) = {
-                        let current = blockchain_clone.lock().unwrap();
-                        (current.difficulty, current.mining_reward)
-                    };
-                    
-                    let response = match Blockchain::load_from_disk(&path, difficulty, mining_reward) {
-                        Ok(loaded_chain) => {
-                            let mut chain = blockchain_clone.lock().unwrap();
-                            *chain = loaded_chain;
-                            BlockchainResponse::Success(format!("Blockchain loaded from {}", path))
-                        }
-                        Err(e) => BlockchainResponse::Error(format!("Failed to load blockchain: {}", e)),
-                    };
-                    let _ = resp_tx.send(response).await;
-                }
-            }
+    // A light node keeps no chain of its own, so it needs neither the data directory nor a handle
+    if matches.is_present("light") {
+        if let Some(subcommand) = matches.subcommand_name() {
+            return Err(ConfigError::invalid("mode", subcommand, "light nodes only sync headers and proofs").into());
         }
-    });
+        let watched = matches.values_of("watch").into_iter().flatten().map(|address| address.to_string());
+        let client = LightClient::new(network_id, params, watched);
+        
+        info!("Starting light node...");
+        return start_light_node(client, matches.value_of("listen_addr").unwrap(), matches.value_of("peer")).await;
+    }
+    
+    // Load state from the data directory; every change made below is persisted back to it
+    let datadir = matches.value_of("datadir").unwrap();
+    let mut blockchain = Blockchain::open(datadir, network_id, params)?;
+    if let Some(path) = matches.value_of("signer_key") {
+        blockchain.set_signer(consensus::load_or_create_key(path)?);
+    }
+    blockchain.mining_threads = match matches.value_of("mining_threads") {
+        Some(_) => parse_arg::<usize>(&matches, "mining_threads", "mining thread count")?,
+        None => std::thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1),
+    };
+    if matches.is_present("prune") {
+        let depth = parse_arg::<u64>(&matches, "prune", "prune depth")?;
+        blockchain.set_prune_depth(depth)?;
+    }
+    // The chain lives on its own task from here; every front end talks to it through a handle
+    let handle = BlockchainHandle::spawn(blockchain);
+    let notify_config = NotifyConfig {
+        block_command: matches.value_of("blocknotify").map(|command| command.to_string()),
+        wallet_command: matches.value_of("walletnotify").map(|command| command.to_string()),
+        watched_addresses: matches
+            .values_of("notify_address")
+            .into_iter()
+            .flatten()
+            .map(|address| address.to_string())
+            .collect(),
+        max_hooks: match parse_arg::<usize>(&matches, "notify_hooks", "notify hook count")? {
+            0 => return Err(ConfigError::invalid("notify hook count", "0", "must be at least 1").into()),
+            hooks => hooks,
+        },
+    };
+    let notifier = (!notify_config.is_empty()).then(|| Notifier::spawn(notify_config, handle.subscribe()));
 
     // Handle CLI commands
     if let Some(matches) = matches.subcommand_matches("mine") {
-        let address = matches.value_of("address").unwrap().to_string();
-        // Create a channel to receive the response
-        let (resp_tx, mut resp_rx) = mpsc::channel::<BlockchainResponse>(1);
-        let _ = tx.send(BlockchainCommand::MineBlock(address, resp_tx)).await;
-        // Wait for the response
-        if let Some(response) = resp_rx.recv().await {
-            match response {
-                BlockchainResponse::Success(msg) => info!("{}", msg),
-                BlockchainResponse::Error(err) => error!("{}", err),
-            }
-        }
+        let address = matches.value_of("address").unwrap();
+        println!("{}", mine(&handle, address).await?);
     } else if let Some(matches) = matches.subcommand_matches("transaction") {
         let sender = matches.value_of("sender").unwrap().to_string();
         let receiver = matches.value_of("receiver").unwrap().to_string();
-        let amount = matches
-            .value_of("amount")
-            .unwrap()
-            .parse::<f32>()
-            .expect("Amount must be a number");
+        let amount = parse_arg::<f32>(matches, "amount", "amount")?;
+        
+        let transaction = Transaction::new(sender, receiver, amount);
         
-        let transaction = Transaction {
-            sender,
-            receiver,
+        println!("{}", submit(&handle, transaction).await?);
+    } else if let Some(matches) = matches.subcommand_matches("stake") {
+        let amount = parse_arg::<f32>(matches, "amount", "amount")?;
+        let transaction = stake_transaction(
+            matches.value_of("owner").unwrap(),
+            matches.value_of("validator").unwrap(),
             amount,
-        };
+        );
         
-        // Create a channel to receive the response
-        let (resp_tx, mut resp_rx) = mpsc::channel::<BlockchainResponse>(1);
-        let _ = tx.send(BlockchainCommand::AddTransaction(transaction, resp_tx)).await;
-        // Wait for the response
-        if let Some(response) = resp_rx.recv().await {
-            match response {
-                BlockchainResponse::Success(msg) => info!("{}", msg),
-                BlockchainResponse::Error(err) => error!("{}", err),
-            }
-        }
+        println!("{}", submit(&handle, transaction).await?);
+    } else if let Some(matches) = matches.subcommand_matches("report") {
+        let transaction = evidence_transaction(
+            matches.value_of("reporter").unwrap(),
+            matches.value_of("first").unwrap(),
+            matches.value_of("second").unwrap(),
+        )?;
+        
+        println!("{}", submit(&handle, transaction).await?);
     } else if let Some(matches) = matches.subcommand_matches("balance") {
-        let address = matches.value_of("address").unwrap().to_string();
+        let address = matches.value_of("address").unwrap();
         
-        // Create a channel to receive the response
-        let (resp_tx, mut resp_rx) = mpsc::channel::<BlockchainResponse>(1);
+        println!("{}", balance(&handle, address).await);
+    } else if let Some(matches) = matches.subcommand_matches("tx") {
+        let id = parse_arg::<Hash256>(matches, "id", "transaction id")?;
         
-        // Send the command with the response channel
-        let _ = tx.send(BlockchainCommand::GetBalance(address, resp_tx)).await;
+        println!("{}", lookup_transaction(&handle, id).await?);
+    } else if let Some(matches) = matches.subcommand_matches("history") {
+        let address = matches.value_of("address").unwrap();
+        let offset = parse_arg::<usize>(matches, "offset", "offset")?;
+        let limit = parse_arg::<usize>(matches, "limit", "limit")?;
         
-        // Wait for the response
-        if let Some(response) = resp_rx.recv().await {
-            match response {
-                BlockchainResponse::Success(msg) => info!("{}", msg),
-                BlockchainResponse::Error(err) => error!("{}", err),
-            }
-        }
+        println!("{}", history(&handle, address, offset, limit).await);
+    } else if let Some(matches) = matches.subcommand_matches("block") {
+        let block_ref = parse_arg::<BlockRef>(matches, "block", "block")?;
+        
+        println!("{}", block(&handle, block_ref).await?);
+    } else if let Some(matches) = matches.subcommand_matches("blocks") {
+        let from = parse_arg::<u64>(matches, "from", "height")?;
+        let limit = parse_arg::<usize>(matches, "limit", "limit")?;
+        
+        println!("{}", blocks(&handle, from, limit).await);
+    } else if matches.subcommand_matches("tip").is_some() {
+        println!("{}", tip(&handle).await);
+    } else if matches.subcommand_matches("pending").is_some() {
+        println!("{}", pending(&handle).await);
+    } else if matches.subcommand_matches("stats").is_some() {
+        println!("{}", stats(&handle).await);
+    } else if let Some(matches) = matches.subcommand_matches("save") {
+        let path = matches.value_of("path").unwrap();
+        let format = parse_arg::<ChainFileFormat>(matches, "format", "chain file format")?;
+        let verify = matches.is_present("verify");
+        
+        println!("{}", save(&handle, path, format, verify).await?);
+    } else if let Some(matches) = matches.subcommand_matches("load") {
+        let path = matches.value_of("path").unwrap();
+        let verify = matches.is_present("verify");
+        
+        println!("{}", load(&handle, path, verify).await?);
+    } else if let Some(matches) = matches.subcommand_matches("snapshot") {
+        let path = matches.value_of("path").unwrap();
+        let height = if matches.is_present("height") {
+            Some(parse_arg::<u64>(matches, "height", "height")?)
+        } else {
+            None
+        };
+        
+        println!("{}", snapshot(&handle, path, height).await?);
+    } else if let Some(matches) = matches.subcommand_matches("bootstrap") {
+        let path = matches.value_of("path").unwrap();
+        let hash = parse_arg::<Hash256>(matches, "hash", "block hash")?;
+        
+        println!("{}", bootstrap(&handle, path, hash).await?);
     } else if matches.is_present("interactive") {
         let listen_addr = matches.value_of("listen_addr").unwrap().to_string();
         let peer = matches.value_of("peer").map(|s| s.to_string());
         
         // Start P2P node in the background if needed
         if peer.is_some() {
-            let blockchain_clone = blockchain.clone();
+            let handle = handle.clone();
             let listen_addr_clone = listen_addr.clone();
             let peer_clone = peer.clone();
             
             tokio::spawn(async move {
                 info!("Starting P2P node in background...");
                 let node_result = start_p2p_node(
-                    blockchain_clone, 
+                    handle,
                     &listen_addr_clone, 
                     peer_clone.as_deref()
                 ).await;
@@ -449,7 +1268,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
         
         // Run interactive mode
-        run_interactive_mode(blockchain, tx_clone).await;
+        run_interactive_mode(handle).await;
     } else {
         // No subcommand, start P2P node
         let listen_addr = matches.value_of("listen_addr").unwrap();
@@ -462,22 +1281,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
         
         // Start P2P Node
-        let node_result = start_p2p_node(blockchain, listen_addr, peer).await;
-        
-        if let Err(e) = node_result {
-            error!("P2P node error: {}", e);
-        }
+        start_p2p_node(handle, listen_addr, peer).await?;
+    }
+
+    // Give hooks for what this run did a chance to finish before exiting
+    if let Some(notifier) = notifier {
+        notifier.finish().await;
     }
 
     Ok(())
 }
-
-// Commands for interacting with the blockchain
-enum BlockchainCommand {
-    AddTransaction(Transaction, mpsc::Sender<BlockchainResponse>),
-    MineBlock(String, mpsc::Sender<BlockchainResponse>),
-    GetBalance(String, mpsc::Sender<BlockchainResponse>),
-    ValidateChain(mpsc::Sender<BlockchainResponse>),
-    SaveChain(String, mpsc::Sender<BlockchainResponse>),
-    LoadChain(String, mpsc::Sender<BlockchainResponse>),
-}
\ No newline at end of file
@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hash::Hash256;
+
+// Binary Merkle tree over SHA-256, pairing an odd node out with itself at each level.
+// As in Bitcoin, that lets a list ending in repeated leaves share its root with the list
+// without them, so blocks holding a transaction twice are rejected by validation.
+// The root of no leaves is the zero hash.
+pub fn merkle_root(leaves: &[Hash256]) -> Hash256 {
+    if leaves.is_empty() {
+        return Hash256::ZERO;
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+// Hashes on the path from a leaf up to the root, enough to show the leaf is in the tree
+// without the other leaves
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    // Position of the leaf; bit i says whether the sibling at level i is on the left
+    pub index: u64,
+    pub siblings: Vec<Hash256>,
+}
+
+// Proof for the leaf at `index`, if there is one
+pub fn merkle_proof(leaves: &[Hash256], index: usize) -> Option<MerkleProof> {
+    if index >= leaves.len() {
+        return None;
+    }
+
+    let mut siblings = Vec::new();
+    let mut level = leaves.to_vec();
+    let mut position = index;
+    while level.len() > 1 {
+        siblings.push(*level.get(position ^ 1).unwrap_or(&level[position]));
+        level = next_level(&level);
+        position /= 2;
+    }
+    Some(MerkleProof {
+        index: index as u64,
+        siblings,
+    })
+}
+
+impl MerkleProof {
+    // Whether the proof leads from `leaf` to `root`
+    pub fn verify(&self, leaf: &Hash256, root: &Hash256) -> bool {
+        // A position past the last leaf the proof can reach
+        if self.index.checked_shr(self.siblings.len() as u32).unwrap_or(0) != 0 {
+            return false;
+        }
+
+        let mut hash = *leaf;
+        for (level, sibling) in self.siblings.iter().enumerate() {
+            hash = if self.index >> level & 1 == 0 {
+                hash_pair(&hash, sibling)
+            } else {
+                hash_pair(sibling, &hash)
+            };
+        }
+        hash == *root
+    }
+}
+
+fn next_level(level: &[Hash256]) -> Vec<Hash256> {
+    level
+        .chunks(2)
+        .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+        .collect()
+}
+
+fn hash_pair(left: &Hash256, right: &Hash256) -> Hash256 {
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(left.as_bytes());
+    data[32..].copy_from_slice(right.as_bytes());
+    Hash256::digest(&data)
+}
@@ -0,0 +1,119 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use sha2::{Digest, Sha256};
+
+use crate::blockchain::{Block, BlockHeader};
+use crate::clock::Clock;
+use crate::consensus::PowAlgorithm;
+use crate::hash::Hash256;
+
+// Search rules for version 1 blocks.
+//
+// A search starts from a block template. Of its header, only these may change:
+// - the nonce, within the worker's own range;
+// - the timestamp, which only moves forward, to the current time;
+// - the transactions root, through the extra-nonce of the coinbase (the last transaction).
+// Version, height, parent hash and every other transaction stay as in the template.
+//
+// Worker `w` only tries nonces in `[w * NONCE_RANGE, (w + 1) * NONCE_RANGE)`, so no two
+// workers ever hash the same header. When a worker exhausts its range it rolls the
+// timestamp if the clock has moved on, and otherwise bumps the coinbase extra-nonce.
+pub const NONCE_RANGE: u64 = 1 << 32;
+
+// How many nonces a worker tries between checks for a block found by another worker
+const STOP_CHECK_INTERVAL: u64 = 1 << 16;
+
+// Hashes a fixed header for many nonces. The header encoding ends with the nonce, so the
+// SHA-256 state after everything before it is computed once and cloned for each attempt;
+// nothing but the nonce bytes is hashed per try.
+#[derive(Clone)]
+pub struct HeaderHasher {
+    midstate: Sha256,
+}
+
+impl HeaderHasher {
+    pub fn new(header: &BlockHeader) -> Self {
+        let mut midstate = Sha256::new();
+        midstate.update(header.encode_prefix());
+        HeaderHasher { midstate }
+    }
+
+    pub fn hash(&self, nonce: u64) -> Hash256 {
+        let mut hasher = self.midstate.clone();
+        hasher.update(nonce.to_le_bytes());
+        Hash256(hasher.finalize().into())
+    }
+}
+
+// Mine a version 1 block template on `threads` workers, returning the sealed block
+pub fn mine(template: &Block, difficulty: usize, algorithm: PowAlgorithm, threads: usize, clock: &dyn Clock) -> Block {
+    let threads = threads.clamp(1, (u64::MAX / NONCE_RANGE) as usize);
+    let found = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads as u64)
+            .map(|worker| {
+                let found = &found;
+                scope.spawn(move || work(template.clone(), worker, difficulty, algorithm, found, clock))
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .filter_map(|worker| worker.join().expect("Mining worker panicked"))
+            .next()
+            .expect("One worker always finds the block")
+    })
+}
+
+fn work(
+    mut block: Block,
+    worker: u64,
+    difficulty: usize,
+    algorithm: PowAlgorithm,
+    found: &AtomicBool,
+    clock: &dyn Clock,
+) -> Option<Block> {
+    let start = worker * NONCE_RANGE;
+
+    loop {
+        let hasher = HeaderHasher::new(&block.header());
+        for nonce in start..start + NONCE_RANGE {
+            if (nonce - start).is_multiple_of(STOP_CHECK_INTERVAL) && found.load(Ordering::Relaxed) {
+                return None;
+            }
+
+            let hash = hasher.hash(nonce);
+            if algorithm.meets_difficulty(&hash, difficulty) {
+                // Only the first worker to get here hands back a block
+                if found.swap(true, Ordering::Relaxed) {
+                    return None;
+                }
+                block.nonce = nonce;
+                block.hash = hash;
+                return Some(block);
+            }
+        }
+
+        roll(&mut block, clock);
+    }
+}
+
+// Move a template whose nonce range is exhausted to a header this worker has not tried yet
+fn roll(block: &mut Block, clock: &dyn Clock) {
+    let now = clock.now();
+    if now > block.timestamp {
+        block.timestamp = now;
+        return;
+    }
+
+    match block.transactions.last_mut() {
+        Some(coinbase) => {
+            coinbase.extra_nonce += 1;
+            block.tx_root = block.calculate_tx_root();
+        }
+        // Nothing to roll but the clock, so run it ahead
+        None => block.timestamp += 1,
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use log::{debug, warn};
+use tokio::process::Command;
+use tokio::sync::broadcast::{self, error::RecvError, error::TryRecvError};
+use tokio::sync::{oneshot, Semaphore};
+use tokio::task::JoinHandle;
+
+use crate::blockchain::{Block, Transaction};
+use crate::events::Event;
+use crate::hash::Hash256;
+
+// Hooks allowed to run at once unless configured otherwise
+pub const DEFAULT_MAX_HOOKS: usize = 4;
+
+// Shell commands run on chain events, like bitcoind's -blocknotify and -walletnotify.
+// Placeholders in a command are replaced before it is handed to `sh -c`:
+//   %s  block hash (block command) or transaction id (wallet command)
+//   %h  block height, or -1 while the transaction is unconfirmed
+//   %b  block hash, or "unconfirmed" (wallet command only)
+//   %a  the watched address that received funds (wallet command only)
+//   %%  a literal %
+#[derive(Debug, Clone)]
+pub struct NotifyConfig {
+    // Run for every block connected to the tip, and once for the new tip when the chain
+    // switches to another branch
+    pub block_command: Option<String>,
+    // Run when a transfer to a watched address enters the mempool and again when it is
+    // confirmed, including by a block the chain switches to
+    pub wallet_command: Option<String>,
+    pub watched_addresses: HashSet<String>,
+    pub max_hooks: usize,
+}
+
+impl NotifyConfig {
+    pub fn is_empty(&self) -> bool {
+        self.block_command.is_none() && self.wallet_command.is_none()
+    }
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        NotifyConfig {
+            block_command: None,
+            wallet_command: None,
+            watched_addresses: HashSet::new(),
+            max_hooks: DEFAULT_MAX_HOOKS,
+        }
+    }
+}
+
+// Runs the configured hooks for events from a chain's event bus on a task of its own.
+// At most `max_hooks` commands run at once; further events wait for a free slot, and a
+// failing or unstartable command is logged without affecting the node.
+pub struct Notifier {
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl Notifier {
+    pub fn spawn(config: NotifyConfig, events: broadcast::Receiver<Event>) -> Self {
+        let (shutdown, stop) = oneshot::channel();
+        let task = tokio::spawn(run(config, events, stop));
+        Notifier { shutdown, task }
+    }
+
+    // Run hooks for the events emitted so far, then wait for every running hook to exit
+    pub async fn finish(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+    }
+}
+
+async fn run(config: NotifyConfig, mut events: broadcast::Receiver<Event>, mut stop: oneshot::Receiver<()>) {
+    // Semaphore::new panics on more permits than it supports, and zero would never run anything
+    let max_hooks = config.max_hooks.clamp(1, Semaphore::MAX_PERMITS);
+    let hooks = Arc::new(Semaphore::new(max_hooks));
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => dispatch(&config, &hooks, &event).await,
+                Err(RecvError::Lagged(missed)) => warn!("Notify hooks fell behind and skipped {} events", missed),
+                Err(RecvError::Closed) => break,
+            },
+            _ = &mut stop => {
+                loop {
+                    match events.try_recv() {
+                        Ok(event) => dispatch(&config, &hooks, &event).await,
+                        Err(TryRecvError::Lagged(missed)) => warn!("Notify hooks fell behind and skipped {} events", missed),
+                        Err(_) => break,
+                    }
+                }
+                break;
+            }
+        }
+    }
+
+    // Every slot is free again once the last running hook has exited
+    let _ = hooks.acquire_many(max_hooks as u32).await;
+}
+
+async fn dispatch(config: &NotifyConfig, hooks: &Arc<Semaphore>, event: &Event) {
+    match event {
+        Event::BlockConnected(block) => {
+            if let Some(template) = &config.block_command {
+                let height = block.index.to_string();
+                let hash = block.hash.to_string();
+                spawn_hook(hooks, expand(template, &[('s', &hash), ('h', &height)])).await;
+            }
+            notify_confirmed(config, hooks, block).await;
+        }
+        // Like bitcoind, a switch to another branch runs the block command once, for the new
+        // tip, but the wallet command for every watched transfer the new branch confirms
+        Event::ChainReplaced { tip, connected, .. } => {
+            if let Some(template) = &config.block_command {
+                let height = tip.index.to_string();
+                let hash = tip.hash.to_string();
+                spawn_hook(hooks, expand(template, &[('s', &hash), ('h', &height)])).await;
+            }
+            for block in connected {
+                notify_confirmed(config, hooks, block).await;
+            }
+        }
+        Event::TxAccepted { id, transaction } => {
+            if let Some(template) = &config.wallet_command {
+                if pays_watched(config, transaction) {
+                    spawn_hook(hooks, wallet_command(template, id, transaction, None)).await;
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// Run the wallet command for each transfer to a watched address that `block` confirms
+async fn notify_confirmed(config: &NotifyConfig, hooks: &Arc<Semaphore>, block: &Block) {
+    if let Some(template) = &config.wallet_command {
+        for transaction in &block.transactions {
+            if pays_watched(config, transaction) {
+                let location = (block.hash.to_string(), block.index.to_string());
+                spawn_hook(hooks, wallet_command(template, &transaction.id(), transaction, Some(location))).await;
+            }
+        }
+    }
+}
+
+fn pays_watched(config: &NotifyConfig, transaction: &Transaction) -> bool {
+    transaction.kind.is_transfer() && config.watched_addresses.contains(&transaction.receiver)
+}
+
+// `location` is the hash and height of the confirming block, if any
+fn wallet_command(template: &str, id: &Hash256, transaction: &Transaction, location: Option<(String, String)>) -> String {
+    let (block, height) = location.unwrap_or_else(|| ("unconfirmed".to_string(), "-1".to_string()));
+    let id = id.to_string();
+    expand(
+        template,
+        &[('s', &id), ('h', &height), ('b', &block), ('a', &transaction.receiver)],
+    )
+}
+
+// Replace each %x placeholder in one pass, so substituted values are never expanded again.
+// Unknown placeholders are left as they are.
+fn expand(template: &str, values: &[(char, &str)]) -> String {
+    let mut command = String::with_capacity(template.len());
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            command.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => command.push('%'),
+            Some(key) => match values.iter().find(|(placeholder, _)| *placeholder == key) {
+                Some((_, value)) => command.push_str(value),
+                None => {
+                    command.push('%');
+                    command.push(key);
+                }
+            },
+            None => command.push('%'),
+        }
+    }
+    command
+}
+
+// Wait for a free slot, then run the command in the background holding it
+async fn spawn_hook(hooks: &Arc<Semaphore>, command: String) {
+    let permit = hooks.clone().acquire_owned().await.expect("Hook slots are never closed");
+    tokio::spawn(async move {
+        debug!("Running notify command `{}`", command);
+        match Command::new("sh").arg("-c").arg(&command).status().await {
+            Ok(status) if status.success() => {}
+            Ok(status) => warn!("Notify command `{}` failed: {}", command, status),
+            Err(e) => warn!("Could not run notify command `{}`: {}", command, e),
+        }
+        drop(permit);
+    });
+}
@@ -1,41 +1,161 @@
-use futures::StreamExt;
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncWrite, AsyncWriteExt, StreamExt};
 use libp2p::{
-    core::upgrade,
+    core::{connection::ConnectionId, upgrade, ProtocolName},
     floodsub::{Floodsub, FloodsubEvent, Topic},
     identity,
     mdns::{Mdns, MdnsEvent},
     mplex,
     noise::{Keypair, NoiseConfig, X25519Spec},
-    swarm::{NetworkBehaviourEventProcess, Swarm, SwarmBuilder},
+    request_response::{
+        ProtocolSupport, RequestResponse, RequestResponseCodec, RequestResponseConfig, RequestResponseEvent,
+        RequestResponseMessage, ResponseChannel,
+    },
+    swarm::{
+        IntoProtocolsHandler, NetworkBehaviourAction, NetworkBehaviourEventProcess, PollParameters, ProtocolsHandler,
+        Swarm, SwarmBuilder, SwarmEvent,
+    },
     tcp::TokioTcpConfig,
-    NetworkBehaviour, PeerId, Transport,
+    Multiaddr, NetworkBehaviour, PeerId, Transport,
 };
-use log::info;
+use log::{error, info, warn};
 use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, sync::Arc, sync::Mutex};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::iter;
+use std::ops::{Deref, DerefMut};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::mpsc;
 
-use crate::blockchain::{Block, Blockchain, Transaction};
+use crate::blockchain::{Block, BlockHeader, BlockOutcome, Transaction};
+use crate::error::{NetworkError, Result, ValidationError};
+use crate::events::Event;
+use crate::handle::BlockchainHandle;
+use crate::hash::Hash256;
+use crate::light::{LightClient, TxProof};
 
-// Define topics for different types of messages
-static BLOCK_TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("blocks"));
+// Most headers sent in one response; a light client asks again for the rest
+const MAX_HEADERS: usize = 2000;
+
+// Most blocks sent in one response to a chain sync; the node asks again for the rest
+const MAX_BLOCKS: usize = 500;
+
+// Largest request or response read on the sync protocol
+const MAX_SYNC_MESSAGE: usize = 64 * 1024 * 1024;
+
+// How long a peer has to answer a sync request
+const SYNC_TIMEOUT: Duration = Duration::from_secs(60);
+
+// How often a light node asks peers for headers above its tip
+const HEADER_SYNC_INTERVAL: Duration = Duration::from_secs(10);
+
+// Define topics for different types of messages. Floodsub drops any message over 2048 bytes,
+// so blocks, chains, headers and proofs travel over the sync protocol instead and only
+// announcements are flooded.
 static TRANSACTION_TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("transactions"));
+// Newly connected blocks, which every node passes on only once it has validated them
+static NEW_BLOCK_TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("new-blocks"));
 
 // Message types for blockchain communication
 #[derive(Debug, Serialize, Deserialize)]
-enum BlockchainMessage {
-    NewBlock(Block),
+pub enum BlockchainMessage {
+    // A block the publisher has connected, which peers that lack it fetch from the publisher
+    NewBlock(BlockHeader),
     NewTransaction(Transaction),
-    ChainRequest,
-    ChainResponse(Vec<Block>),
+}
+
+// Requests a node answers for one peer over the sync protocol
+#[derive(Debug, Serialize, Deserialize)]
+pub enum SyncRequest {
+    // Blocks from a height up, at most MAX_BLOCKS of them
+    Blocks(u64),
+    // The block with this hash, body included
+    Block(Hash256),
+    // Headers from a height up, at most MAX_HEADERS of them, for light clients
+    Headers(u64),
+    // Merkle proofs of the confirmed transactions of an address, for light clients
+    Proofs(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum SyncResponse {
+    // Empty when the node has pruned bodies, since peers could not check them
+    Blocks(Vec<Block>),
+    Block(Option<Block>),
+    Headers(Vec<BlockHeader>),
+    Proofs(Vec<TxProof>),
+}
+
+#[derive(Debug, Clone)]
+struct SyncProtocol;
+
+impl ProtocolName for SyncProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/rustchain/sync/1"
+    }
+}
+
+// Each request and response is one length-prefixed JSON message
+#[derive(Debug, Clone)]
+struct SyncCodec;
+
+#[async_trait]
+impl RequestResponseCodec for SyncCodec {
+    type Protocol = SyncProtocol;
+    type Request = SyncRequest;
+    type Response = SyncResponse;
+
+    async fn read_request<T>(&mut self, _: &SyncProtocol, io: &mut T) -> io::Result<SyncRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_message(io).await
+    }
+
+    async fn read_response<T>(&mut self, _: &SyncProtocol, io: &mut T) -> io::Result<SyncResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_message(io).await
+    }
+
+    async fn write_request<T>(&mut self, _: &SyncProtocol, io: &mut T, request: SyncRequest) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_message(io, &request).await
+    }
+
+    async fn write_response<T>(&mut self, _: &SyncProtocol, io: &mut T, response: SyncResponse) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_message(io, &response).await
+    }
+}
+
+async fn read_message<T: DeserializeOwned>(io: &mut (impl AsyncRead + Unpin + Send)) -> io::Result<T> {
+    let data = upgrade::read_length_prefixed(io, MAX_SYNC_MESSAGE).await?;
+    serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+async fn write_message(io: &mut (impl AsyncWrite + Unpin + Send), message: &impl Serialize) -> io::Result<()> {
+    let data = serde_json::to_vec(message).expect("Failed to serialize message");
+    upgrade::write_length_prefixed(io, data).await?;
+    io.close().await
 }
 
 // Define behavior for our P2P network
 #[derive(NetworkBehaviour)]
 #[behaviour(event_process = true)]
 struct BlockchainBehaviour {
-    floodsub: Floodsub,
+    floodsub: ValidatedRelay,
+    sync: RequestResponse<SyncCodec>,
     mdns: Mdns,
     #[behaviour(ignore)]
     response_sender: mpsc::UnboundedSender<BlockchainResponse>,
@@ -44,8 +164,13 @@ struct BlockchainBehaviour {
 // Define response types from the network
 #[derive(Debug)]
 enum BlockchainResponse {
-    Blocks(Vec<Block>),
+    // A block announced by `peer`, which has its body
+    NewBlock { peer: PeerId, header: BlockHeader },
     Transactions(Vec<Transaction>),
+    // A peer's sync request, answered on `channel`
+    SyncRequested { request: SyncRequest, channel: ResponseChannel<SyncResponse> },
+    Synced { peer: PeerId, response: SyncResponse },
+    SyncFailed(PeerId),
     PeerDiscovered(PeerId),
     PeerExpired(PeerId),
 }
@@ -56,23 +181,125 @@ impl NetworkBehaviourEventProcess<FloodsubEvent> for BlockchainBehaviour {
         if let FloodsubEvent::Message(message) = event {
             if let Ok(blockchain_message) = serde_json::from_slice::<BlockchainMessage>(&message.data) {
                 match blockchain_message {
-                    BlockchainMessage::NewBlock(block) => {
-                        info!("Received new block from {:?}: {:?}", message.source, block);
-                        // TODO: Validate and add block to chain
+                    BlockchainMessage::NewBlock(header) => {
+                        info!("Received new block from {:?}: {}", message.source, header.hash);
+                        let _ = self.response_sender.send(BlockchainResponse::NewBlock {
+                            peer: message.source,
+                            header,
+                        });
                     }
                     BlockchainMessage::NewTransaction(transaction) => {
                         info!("Received new transaction from {:?}: {:?}", message.source, transaction);
-                        // TODO: Validate and add transaction to mempool
-                    }
-                    BlockchainMessage::ChainRequest => {
-                        info!("Received chain request from {:?}", message.source);
-                        // TODO: Send chain response
+                        let _ = self.response_sender.send(BlockchainResponse::Transactions(vec![transaction]));
                     }
-                    BlockchainMessage::ChainResponse(blocks) => {
-                        info!("Received chain response from {:?} with {} blocks", message.source, blocks.len());
-                        let _ = self.response_sender.send(BlockchainResponse::Blocks(blocks));
+                }
+            }
+        }
+    }
+}
+
+// Handle requests from peers and their answers to ours
+impl NetworkBehaviourEventProcess<RequestResponseEvent<SyncRequest, SyncResponse>> for BlockchainBehaviour {
+    fn inject_event(&mut self, event: RequestResponseEvent<SyncRequest, SyncResponse>) {
+        match event {
+            RequestResponseEvent::Message { peer, message } => match message {
+                RequestResponseMessage::Request { request, channel, .. } => {
+                    info!("Received sync request from {:?}: {:?}", peer, request);
+                    let _ = self.response_sender.send(BlockchainResponse::SyncRequested { request, channel });
+                }
+                RequestResponseMessage::Response { response, .. } => {
+                    let _ = self.response_sender.send(BlockchainResponse::Synced { peer, response });
+                }
+            },
+            RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+                warn!("Sync request to {:?} failed: {}", peer, error);
+                let _ = self.response_sender.send(BlockchainResponse::SyncFailed(peer));
+            }
+            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                warn!("Sync request from {:?} failed: {}", peer, error);
+            }
+            RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
+}
+
+// Floodsub, except that messages on the new block and transaction topics are only relayed
+// by publishing them again. Floodsub forwards whatever it receives before the node has
+// looked at it, so left alone it would pass invalid blocks and transactions on; instead a
+// node announces each block itself once it has connected it, and each transaction once it
+// has entered the mempool.
+struct ValidatedRelay {
+    floodsub: Floodsub,
+    local_peer_id: PeerId,
+}
+
+impl ValidatedRelay {
+    fn new(local_peer_id: PeerId) -> Self {
+        ValidatedRelay {
+            floodsub: Floodsub::new(local_peer_id),
+            local_peer_id,
+        }
+    }
+}
+
+impl Deref for ValidatedRelay {
+    type Target = Floodsub;
+
+    fn deref(&self) -> &Floodsub {
+        &self.floodsub
+    }
+}
+
+impl DerefMut for ValidatedRelay {
+    fn deref_mut(&mut self) -> &mut Floodsub {
+        &mut self.floodsub
+    }
+}
+
+impl libp2p::swarm::NetworkBehaviour for ValidatedRelay {
+    type ProtocolsHandler = <Floodsub as libp2p::swarm::NetworkBehaviour>::ProtocolsHandler;
+    type OutEvent = FloodsubEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        self.floodsub.new_handler()
+    }
+
+    fn inject_connected(&mut self, peer_id: &PeerId) {
+        self.floodsub.inject_connected(peer_id);
+    }
+
+    fn inject_disconnected(&mut self, peer_id: &PeerId) {
+        self.floodsub.inject_disconnected(peer_id);
+    }
+
+    fn inject_event(
+        &mut self,
+        peer_id: PeerId,
+        connection: ConnectionId,
+        event: <<Self::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::OutEvent,
+    ) {
+        self.floodsub.inject_event(peer_id, connection, event);
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        params: &mut impl PollParameters,
+    ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ProtocolsHandler>> {
+        loop {
+            match self.floodsub.poll(cx, params) {
+                Poll::Ready(NetworkBehaviourAction::NotifyHandler { peer_id, handler, mut event }) => {
+                    let local_peer_id = self.local_peer_id;
+                    event.messages.retain(|message| {
+                        message.source == local_peer_id
+                            || !(message.topics.contains(&NEW_BLOCK_TOPIC) || message.topics.contains(&TRANSACTION_TOPIC))
+                    });
+                    if event.messages.is_empty() && event.subscriptions.is_empty() {
+                        continue;
                     }
+                    return Poll::Ready(NetworkBehaviourAction::NotifyHandler { peer_id, handler, event });
                 }
+                other => return other,
             }
         }
     }
@@ -107,120 +334,136 @@ pub struct P2P {
     swarm: Swarm<BlockchainBehaviour>,
     response_receiver: mpsc::UnboundedReceiver<BlockchainResponse>,
     known_peers: HashSet<PeerId>,
+    connected_peers: HashSet<PeerId>,
+    // Blocks received so far from each peer whose chain is being synced page by page
+    chain_syncs: HashMap<PeerId, Vec<Block>>,
+    blockchain: BlockchainHandle,
 }
 
 impl P2P {
-    pub async fn new() -> Self {
-        // Create a random key for our identity
-        let local_key = identity::Keypair::generate_ed25519();
-        let local_peer_id = PeerId::from(local_key.public());
-        info!("Local peer id: {:?}", local_peer_id);
-
-        // Set up noise for authenticated encryption
-        let noise_keys = Keypair::<X25519Spec>::new()
-            .into_authentic(&local_key)
-            .expect("Failed to create noise keys");
-
-        // Set up TCP transport with noise and mplex for multiplexing
-        let transport = TokioTcpConfig::new()
-            .nodelay(true)
-            .upgrade(upgrade::Version::V1)
-            .authenticate(NoiseConfig::xx(noise_keys).into_authenticated())
-            .multiplex(mplex::MplexConfig::new())
-            .boxed();
-
-        // Create a channel for handling responses
-        let (response_sender, response_receiver) = mpsc::unbounded_channel();
-
-        // Create MDNS service for peer discovery on local network
-        let mdns = Mdns::new(Default::default())
-            .await
-            .expect("Failed to create MDNS service");
-
-        // Set up FloodSub for message broadcasting
-        let mut floodsub = Floodsub::new(local_peer_id);
-        floodsub.subscribe(BLOCK_TOPIC.clone());
-        floodsub.subscribe(TRANSACTION_TOPIC.clone());
-
-        // Create the network behavior
-        let behaviour = BlockchainBehaviour {
-            floodsub,
-            mdns,
-            response_sender,
-        };
-
-        // Build the swarm
-        let swarm = SwarmBuilder::new(transport, behaviour, local_peer_id)
-            .executor(Box::new(|fut| {
-                tokio::spawn(fut);
-            }))
-            .build();
+    pub async fn new(blockchain: BlockchainHandle) -> Result<Self, NetworkError> {
+        let (swarm, response_receiver) = build_swarm().await?;
 
-        Self {
+        Ok(Self {
             swarm,
             response_receiver,
             known_peers: HashSet::new(),
-        }
+            connected_peers: HashSet::new(),
+            chain_syncs: HashMap::new(),
+            blockchain,
+        })
     }
 
     // Start listening on the given address
-    pub async fn start(&mut self, addr: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let multiaddr = addr.parse()?;
-        Swarm::listen_on(&mut self.swarm, multiaddr)
-            .expect("Failed to listen on multiaddr");
-        
-        Ok(())
+    pub async fn start(&mut self, addr: &str) -> Result<(), NetworkError> {
+        listen(&mut self.swarm, addr)
     }
 
     // Connect to a peer
-    pub async fn connect(&mut self, addr: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let multiaddr = addr.parse()?;
-        Swarm::dial_addr(&mut self.swarm, multiaddr)
-            .expect("Failed to dial address");
-        
-        Ok(())
+    pub async fn connect(&mut self, addr: &str) -> Result<(), NetworkError> {
+        dial(&mut self.swarm, addr)
     }
 
-    // Broadcast a new block to the network
+    // Announce a new block to the network; peers fetch its body from us
     pub fn broadcast_block(&mut self, block: Block) {
-        let message = BlockchainMessage::NewBlock(block);
-        let json = serde_json::to_string(&message).expect("Failed to serialize message");
-        self.swarm.behaviour_mut().floodsub.publish(BLOCK_TOPIC.clone(), json.as_bytes());
+        publish(&mut self.swarm, &NEW_BLOCK_TOPIC, &BlockchainMessage::NewBlock(block.header()));
     }
 
     // Broadcast a new transaction to the network
     pub fn broadcast_transaction(&mut self, transaction: Transaction) {
-        let message = BlockchainMessage::NewTransaction(transaction);
-        let json = serde_json::to_string(&message).expect("Failed to serialize message");
-        self.swarm.behaviour_mut().floodsub.publish(TRANSACTION_TOPIC.clone(), json.as_bytes());
+        publish(&mut self.swarm, &TRANSACTION_TOPIC, &BlockchainMessage::NewTransaction(transaction));
     }
 
-    // Request the blockchain from peers
+    // Request the blockchain from every connected peer
     pub fn request_blockchain(&mut self) {
-        let message = BlockchainMessage::ChainRequest;
-        let json = serde_json::to_string(&message).expect("Failed to serialize message");
-        self.swarm.behaviour_mut().floodsub.publish(BLOCK_TOPIC.clone(), json.as_bytes());
+        let peers: Vec<PeerId> = self.connected_peers.iter().cloned().collect();
+        for peer in peers {
+            self.request_chain(peer);
+        }
+    }
+
+    // Start syncing a peer's whole chain, unless that is already under way
+    fn request_chain(&mut self, peer: PeerId) {
+        if let Entry::Vacant(entry) = self.chain_syncs.entry(peer) {
+            entry.insert(Vec::new());
+            self.swarm.behaviour_mut().sync.send_request(&peer, SyncRequest::Blocks(0));
+        }
     }
 
     // Main event loop to process network events
     pub async fn run(&mut self) {
+        let mut events = self.blockchain.subscribe();
+        
         loop {
             tokio::select! {
+                // Announce every block connected to the tip, which only happens to blocks mined
+                // here or accepted through submit_block, so peers only ever hear of blocks this
+                // node validated. Blocks arriving with a replaced chain are not announced, and a
+                // block without its body (every full block pays a reward) would only be rejected.
+                event = events.recv() => match event {
+                    Ok(Event::BlockConnected(block)) if !block.transactions.is_empty() => self.broadcast_block(block),
+                    // Likewise every transaction entering the mempool, whether made here or received
+                    Ok(Event::TxAccepted { transaction, .. }) => self.broadcast_transaction(transaction),
+                    Ok(_) => {},
+                    Err(RecvError::Lagged(missed)) => warn!("Missed {} chain events; some blocks were not announced", missed),
+                    // The chain is shutting down, so there is nothing left to serve
+                    Err(RecvError::Closed) => {
+                        info!("Event bus closed, stopping the P2P node");
+                        break;
+                    },
+                },
                 event = self.swarm.select_next_some() => {
                     info!("Swarm event: {:?}", event);
+                    // Announce a peer on its first connection and when its last one closes, and
+                    // sync its chain as soon as it connects
+                    match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, num_established, .. } if num_established.get() == 1 => {
+                            self.connected_peers.insert(peer_id);
+                            self.blockchain.events().emit(Event::PeerConnected(peer_id));
+                            self.request_chain(peer_id);
+                        }
+                        SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. } => {
+                            self.connected_peers.remove(&peer_id);
+                            self.chain_syncs.remove(&peer_id);
+                            self.blockchain.events().emit(Event::PeerDisconnected(peer_id));
+                        }
+                        _ => {}
+                    }
                 },
                 response = self.response_receiver.recv() => {
                     if let Some(response) = response {
                         match response {
-                            BlockchainResponse::Blocks(blocks) => {
-                                // Process received blocks
-                                // TODO: Validate chain and update if needed
-                                info!("Received blocks: {}", blocks.len());
+                            BlockchainResponse::NewBlock { peer, header } => {
+                                if self.blockchain.block_by_hash(header.hash).await.is_none() {
+                                    self.swarm.behaviour_mut().sync.send_request(&peer, SyncRequest::Block(header.hash));
+                                }
+                            },
+                            BlockchainResponse::SyncRequested { request, channel } => {
+                                let response = answer(&self.blockchain, request).await;
+                                // The peer may have gone away meanwhile
+                                let _ = self.swarm.behaviour_mut().sync.send_response(channel, response);
+                            },
+                            BlockchainResponse::Synced { peer, response: SyncResponse::Blocks(blocks) } => {
+                                self.add_chain_page(peer, blocks).await;
+                            },
+                            BlockchainResponse::Synced { peer, response: SyncResponse::Block(Some(block)) } => {
+                                self.submit_peer_block(peer, block).await;
+                            },
+                            // Answers meant for light clients, or empty ones
+                            BlockchainResponse::Synced { .. } => {},
+                            BlockchainResponse::SyncFailed(peer) => {
+                                self.chain_syncs.remove(&peer);
                             },
                             BlockchainResponse::Transactions(transactions) => {
-                                // Process received transactions
-                                // TODO: Add to mempool
-                                info!("Received transactions: {}", transactions.len());
+                                // Announced onwards through the chain's events once in the mempool
+                                for transaction in transactions {
+                                    let id = transaction.id();
+                                    match self.blockchain.receive_transaction(transaction).await {
+                                        Ok(true) => info!("Added transaction {} from peer to the mempool", id),
+                                        Ok(false) => {},
+                                        Err(e) => warn!("Rejecting transaction {} from peer: {}", id, e),
+                                    }
+                                }
                             },
                             BlockchainResponse::PeerDiscovered(peer) => {
                                 self.known_peers.insert(peer);
@@ -235,20 +478,307 @@ impl P2P {
         }
     }
 
+    // Collect a page of a peer's chain and ask for the next one, or once a page comes back
+    // short, adopt the peer's chain if fork choice prefers it
+    async fn add_chain_page(&mut self, peer: PeerId, blocks: Vec<Block>) {
+        let full_page = blocks.len() >= MAX_BLOCKS;
+        let chain = match self.chain_syncs.get_mut(&peer) {
+            Some(chain) => chain,
+            None => return,
+        };
+        chain.extend(blocks);
+        if full_page {
+            let from = chain.len() as u64;
+            self.swarm.behaviour_mut().sync.send_request(&peer, SyncRequest::Blocks(from));
+            return;
+        }
+
+        let blocks = self.chain_syncs.remove(&peer).unwrap_or_default();
+        if blocks.is_empty() {
+            return;
+        }
+        info!("Received blocks: {}", blocks.len());
+        match self.blockchain.replace_chain(blocks).await {
+            Ok(Some(tip)) => info!("Switched to peer chain, tip is now {}", tip),
+            Ok(None) => info!("Kept current chain"),
+            Err(e) => error!("Failed to store peer chain: {}", e),
+        }
+    }
+
+    async fn submit_peer_block(&mut self, peer: PeerId, block: Block) {
+        let hash = block.hash;
+        match self.blockchain.submit_block(block).await {
+            // Announced onwards through the chain's events once connected
+            Ok(BlockOutcome::Connected) => info!("Connected block {} from peer", hash),
+            Ok(BlockOutcome::Known) => {},
+            Ok(BlockOutcome::Stale) => info!("Block {} from peer is on a fork below our tip", hash),
+            Ok(BlockOutcome::Orphan) => {
+                info!("Block {} from peer has an unknown parent, requesting the chain", hash);
+                self.request_chain(peer);
+            },
+            Err(e) => warn!("Rejecting block {} from peer: {}", hash, e),
+        }
+    }
+
     // Get the list of currently connected peers
     pub fn peers(&self) -> Vec<PeerId> {
         self.known_peers.iter().cloned().collect()
     }
 }
 
+// What a full node sends back for a peer's sync request
+async fn answer(blockchain: &BlockchainHandle, request: SyncRequest) -> SyncResponse {
+    match request {
+        // Peers cannot check a chain with pruned bodies, so only a full one is offered
+        SyncRequest::Blocks(from) => {
+            if blockchain.tip().await.pruned_height == 0 {
+                SyncResponse::Blocks(blockchain.blocks(from, MAX_BLOCKS).await)
+            } else {
+                SyncResponse::Blocks(Vec::new())
+            }
+        }
+        // A block without its body (every full block pays a reward) would only be rejected
+        SyncRequest::Block(hash) => {
+            let block = blockchain.block_by_hash(hash).await;
+            SyncResponse::Block(block.filter(|block| !block.transactions.is_empty()))
+        }
+        SyncRequest::Headers(from) => SyncResponse::Headers(blockchain.headers(from, MAX_HEADERS).await),
+        SyncRequest::Proofs(address) => SyncResponse::Proofs(blockchain.prove_transactions(&address).await),
+    }
+}
+
+// Network side of a light client: keeps its header chain in step with its peers' and asks
+// them for proofs of the watched addresses' transactions whenever the tip moves
+pub struct LightNode {
+    swarm: Swarm<BlockchainBehaviour>,
+    response_receiver: mpsc::UnboundedReceiver<BlockchainResponse>,
+    connected_peers: HashSet<PeerId>,
+    client: LightClient,
+}
+
+impl LightNode {
+    pub async fn new(client: LightClient) -> Result<Self, NetworkError> {
+        let (swarm, response_receiver) = build_swarm().await?;
+
+        Ok(Self {
+            swarm,
+            response_receiver,
+            connected_peers: HashSet::new(),
+            client,
+        })
+    }
+
+    pub async fn start(&mut self, addr: &str) -> Result<(), NetworkError> {
+        listen(&mut self.swarm, addr)
+    }
+
+    pub async fn connect(&mut self, addr: &str) -> Result<(), NetworkError> {
+        dial(&mut self.swarm, addr)
+    }
+
+    pub fn client(&self) -> &LightClient {
+        &self.client
+    }
+
+    pub async fn run(&mut self) {
+        let mut sync = tokio::time::interval(HEADER_SYNC_INTERVAL);
+        // Set when a peer's headers fork below the heights we asked for
+        let mut from_genesis = false;
+
+        loop {
+            tokio::select! {
+                // Ask each peer for headers as soon as it connects
+                event = self.swarm.select_next_some() => {
+                    info!("Swarm event: {:?}", event);
+                    match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, num_established, .. } if num_established.get() == 1 => {
+                            self.connected_peers.insert(peer_id);
+                            let from = self.client.sync_height();
+                            self.swarm.behaviour_mut().sync.send_request(&peer_id, SyncRequest::Headers(from));
+                        }
+                        SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. } => {
+                            self.connected_peers.remove(&peer_id);
+                        }
+                        _ => {}
+                    }
+                },
+                _ = sync.tick() => {
+                    let from = if from_genesis { 0 } else { self.client.sync_height() };
+                    self.request_from_peers(|| SyncRequest::Headers(from));
+                },
+                response = self.response_receiver.recv() => match response {
+                    Some(BlockchainResponse::Synced { peer, response: SyncResponse::Headers(headers) }) => {
+                        let more = headers.len() >= MAX_HEADERS;
+                        match self.client.add_headers(headers) {
+                            Ok(true) => {
+                                from_genesis = false;
+                                self.on_new_tip(peer, more);
+                            }
+                            Ok(false) => {}
+                            Err(e) => {
+                                warn!("Rejecting headers from peer: {}", e);
+                                from_genesis = matches!(e, ValidationError::BrokenLink { .. });
+                            }
+                        }
+                    },
+                    Some(BlockchainResponse::Synced { response: SyncResponse::Proofs(proofs), .. }) => {
+                        for proof in proofs {
+                            match self.client.add_proof(proof.clone()) {
+                                Ok(true) => info!(
+                                    "Transaction {} ({} -> {}, {}) proven in block {} at height {} with {} confirmation(s)",
+                                    proof.transaction.id(),
+                                    proof.transaction.sender,
+                                    proof.transaction.receiver,
+                                    proof.transaction.amount,
+                                    proof.block_hash,
+                                    proof.height,
+                                    self.client.confirmations(&proof),
+                                ),
+                                Ok(false) => {}
+                                Err(e) => warn!("Rejecting proof from peer: {}", e),
+                            }
+                        }
+                    },
+                    // Light nodes have no blocks to offer
+                    Some(BlockchainResponse::SyncRequested { request, channel }) => {
+                        let response = match request {
+                            SyncRequest::Blocks(_) => SyncResponse::Blocks(Vec::new()),
+                            SyncRequest::Block(_) => SyncResponse::Block(None),
+                            SyncRequest::Headers(_) => SyncResponse::Headers(Vec::new()),
+                            SyncRequest::Proofs(_) => SyncResponse::Proofs(Vec::new()),
+                        };
+                        let _ = self.swarm.behaviour_mut().sync.send_response(channel, response);
+                    },
+                    // Nor a chain to offer blocks to
+                    Some(_) => {},
+                    None => break,
+                },
+            }
+        }
+    }
+
+    // Ask `peer` for the rest of the headers if its last response was cut short, and every
+    // peer for fresh proofs of every watched address, which may have moved into the new blocks
+    fn on_new_tip(&mut self, peer: PeerId, more: bool) {
+        if let Some(tip) = self.client.tip() {
+            info!("Header chain tip is now {} at height {}", tip.hash, tip.index);
+        }
+        if more {
+            let from = self.client.sync_height();
+            self.swarm.behaviour_mut().sync.send_request(&peer, SyncRequest::Headers(from));
+        }
+        let watched: Vec<String> = self.client.watched().cloned().collect();
+        for address in watched {
+            self.request_from_peers(|| SyncRequest::Proofs(address.clone()));
+        }
+    }
+
+    fn request_from_peers(&mut self, request: impl Fn() -> SyncRequest) {
+        for peer in &self.connected_peers {
+            self.swarm.behaviour_mut().sync.send_request(peer, request());
+        }
+    }
+}
+
+// A swarm with a fresh identity, and the channel its behaviour reports messages on
+async fn build_swarm() -> Result<
+    (Swarm<BlockchainBehaviour>, mpsc::UnboundedReceiver<BlockchainResponse>),
+    NetworkError,
+> {
+    // Create a random key for our identity
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_peer_id = PeerId::from(local_key.public());
+    info!("Local peer id: {:?}", local_peer_id);
+
+    // Set up noise for authenticated encryption
+    let noise_keys = Keypair::<X25519Spec>::new()
+        .into_authentic(&local_key)
+        .map_err(|e| NetworkError::Transport(format!("Failed to create noise keys: {}", e)))?;
+
+    // Set up TCP transport with noise and mplex for multiplexing
+    let transport = TokioTcpConfig::new()
+        .nodelay(true)
+        .upgrade(upgrade::Version::V1)
+        .authenticate(NoiseConfig::xx(noise_keys).into_authenticated())
+        .multiplex(mplex::MplexConfig::new())
+        .boxed();
+
+    // Create a channel for handling responses
+    let (response_sender, response_receiver) = mpsc::unbounded_channel();
+
+    // Create MDNS service for peer discovery on local network
+    let mdns = Mdns::new(Default::default())
+        .await
+        .map_err(|e| NetworkError::Transport(format!("Failed to create MDNS service: {}", e)))?;
+
+    // Set up FloodSub for message broadcasting
+    let mut floodsub = ValidatedRelay::new(local_peer_id);
+    floodsub.subscribe(NEW_BLOCK_TOPIC.clone());
+    floodsub.subscribe(TRANSACTION_TOPIC.clone());
+
+    // Set up the sync protocol for requests answered by a single peer
+    let mut sync_config = RequestResponseConfig::default();
+    sync_config.set_request_timeout(SYNC_TIMEOUT);
+    let sync = RequestResponse::new(SyncCodec, iter::once((SyncProtocol, ProtocolSupport::Full)), sync_config);
+
+    // Create the network behavior
+    let behaviour = BlockchainBehaviour {
+        floodsub,
+        sync,
+        mdns,
+        response_sender,
+    };
+
+    // Build the swarm
+    let swarm = SwarmBuilder::new(transport, behaviour, local_peer_id)
+        .executor(Box::new(|fut| {
+            tokio::spawn(fut);
+        }))
+        .build();
+
+    Ok((swarm, response_receiver))
+}
+
+fn listen(swarm: &mut Swarm<BlockchainBehaviour>, addr: &str) -> Result<(), NetworkError> {
+    let multiaddr = parse_address(addr)?;
+    Swarm::listen_on(swarm, multiaddr).map_err(|e| NetworkError::Listen {
+        address: addr.to_string(),
+        reason: e.to_string(),
+    })?;
+
+    Ok(())
+}
+
+fn dial(swarm: &mut Swarm<BlockchainBehaviour>, addr: &str) -> Result<(), NetworkError> {
+    let multiaddr = parse_address(addr)?;
+    Swarm::dial_addr(swarm, multiaddr).map_err(|e| NetworkError::Dial {
+        address: addr.to_string(),
+        reason: e.to_string(),
+    })?;
+
+    Ok(())
+}
+
+fn publish(swarm: &mut Swarm<BlockchainBehaviour>, topic: &Topic, message: &BlockchainMessage) {
+    let json = serde_json::to_string(message).expect("Failed to serialize message");
+    swarm.behaviour_mut().floodsub.publish(topic.clone(), json.as_bytes());
+}
+
+fn parse_address(addr: &str) -> Result<Multiaddr, NetworkError> {
+    addr.parse().map_err(|e: libp2p::multiaddr::Error| NetworkError::InvalidAddress {
+        address: addr.to_string(),
+        reason: e.to_string(),
+    })
+}
+
 // Example of how to use the P2P module with a blockchain
 pub async fn start_p2p_node(
-    blockchain: Arc<Mutex<Blockchain>>,
+    blockchain: BlockchainHandle,
     listen_address: &str,
     known_peer: Option<&str>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<()> {
     // Create and start a new P2P node
-    let mut p2p = P2P::new().await;
+    let mut p2p = P2P::new(blockchain).await?;
     p2p.start(listen_address).await?;
     
     // Connect to a known peer if specified
@@ -256,11 +786,21 @@ pub async fn start_p2p_node(
         p2p.connect(peer).await?;
     }
     
-    // Request the current blockchain from peers
-    p2p.request_blockchain();
-    
-    // Start the main event loop
+    // Start the main event loop, which syncs each peer's chain once connected
     p2p.run().await;
     
     Ok(())
-}
\ No newline at end of file
+}
+// Run a light node that syncs headers and proofs of its watched addresses from its peers
+pub async fn start_light_node(client: LightClient, listen_address: &str, known_peer: Option<&str>) -> Result<()> {
+    let mut node = LightNode::new(client).await?;
+    node.start(listen_address).await?;
+
+    if let Some(peer) = known_peer {
+        node.connect(peer).await?;
+    }
+
+    node.run().await;
+
+    Ok(())
+}
@@ -0,0 +1,598 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use sha2::{Digest, Sha256};
+
+use crate::blockchain::{Block, BlockHeader, ChainParams, PrunedState, Snapshot, Transaction};
+use crate::error::{ConfigError, Result, StorageError};
+use crate::hash::Hash256;
+
+const DATA_FILE: &str = "blocks.dat";
+const INDEX_FILE: &str = "blocks.idx";
+const STATE_FILE: &str = "chainstate.json";
+// Replacement log and index written by compaction; the index appearing commits the swap
+const COMPACT_DATA_FILE: &str = "blocks.dat.new";
+const COMPACT_INDEX_FILE: &str = "blocks.idx.new";
+
+// Index record layout: data offset (u64 LE), record length (u32 LE), block hash (64 hex bytes)
+const HASH_LEN: usize = 64;
+const INDEX_RECORD_LEN: usize = 8 + 4 + HASH_LEN;
+
+#[derive(Debug, Clone)]
+struct IndexEntry {
+    offset: u64,
+    len: u32,
+    hash: Hash256,
+}
+
+// Append-only block log with a fixed-size height index.
+//
+// Blocks are appended to `blocks.dat` as serialized records and located through
+// `blocks.idx`, whose n-th record points at the block at height n. Writing a block
+// only touches the end of both files, and any block can be read back with one seek.
+// Pruned bodies are given back by compaction, which rewrites both files.
+#[derive(Debug)]
+pub struct BlockStore {
+    dir: PathBuf,
+    data: File,
+    index: File,
+    entries: Vec<IndexEntry>,
+    by_hash: HashMap<Hash256, u64>,
+    // Blocks below this height are stored as headers only, as far as this process knows
+    compacted: u64,
+}
+
+impl BlockStore {
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, StorageError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        finish_compaction(&dir)?;
+
+        let open = |name: &str| {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(dir.join(name))
+        };
+        let data = open(DATA_FILE)?;
+        let mut index = open(INDEX_FILE)?;
+
+        let mut raw = Vec::new();
+        index.read_to_end(&mut raw)?;
+
+        // Drop a torn index record or records pointing past the end of the data file,
+        // which is what an interrupted append leaves behind, and anything after a garbled hash
+        let data_len = data.metadata()?.len();
+        let mut entries = Vec::new();
+        for record in raw.chunks_exact(INDEX_RECORD_LEN) {
+            let offset = u64::from_le_bytes(record[0..8].try_into().unwrap());
+            let len = u32::from_le_bytes(record[8..12].try_into().unwrap());
+            let end = offset.checked_add(len as u64).ok_or_else(|| {
+                StorageError::Corrupt(format!("index record at height {} ends past the largest offset", entries.len()))
+            })?;
+            if end > data_len {
+                break;
+            }
+            let hash = match std::str::from_utf8(&record[12..]).ok().and_then(|hex| hex.parse().ok()) {
+                Some(hash) => hash,
+                None => break,
+            };
+            entries.push(IndexEntry { offset, len, hash });
+        }
+
+        let mut store = BlockStore {
+            dir,
+            data,
+            index,
+            entries: Vec::new(),
+            by_hash: HashMap::new(),
+            compacted: 0,
+        };
+        for entry in entries {
+            store.by_hash.insert(entry.hash, store.entries.len() as u64);
+            store.entries.push(entry);
+        }
+        store.truncate_files()?;
+
+        Ok(store)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn len(&self) -> u64 {
+        self.entries.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn hash_at(&self, height: u64) -> Option<Hash256> {
+        self.entries.get(height as usize).map(|entry| entry.hash)
+    }
+
+    pub fn append(&mut self, block: &Block) -> Result<(), StorageError> {
+        if block.index != self.len() {
+            return Err(StorageError::UnexpectedHeight {
+                expected: self.len(),
+                found: block.index,
+            });
+        }
+
+        let record = serde_json::to_vec(block)?;
+        let offset = self.data.seek(SeekFrom::End(0))?;
+        self.data.write_all(&record)?;
+        self.data.sync_data()?;
+
+        let entry = IndexEntry {
+            offset,
+            len: record.len() as u32,
+            hash: block.hash,
+        };
+        self.index.seek(SeekFrom::End(0))?;
+        self.index.write_all(&index_record(&entry))?;
+        self.index.sync_data()?;
+
+        self.by_hash.insert(entry.hash, block.index);
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    pub fn compacted(&self) -> u64 {
+        self.compacted
+    }
+
+    // Take the height a previous process compacted the log to, as recorded in the node state
+    pub fn set_compacted(&mut self, height: u64) {
+        self.compacted = height.min(self.len());
+    }
+
+    pub fn get(&self, height: u64) -> Result<Option<Block>, StorageError> {
+        match self.entries.get(height as usize) {
+            Some(entry) => Ok(Some(serde_json::from_slice(&self.read_record(entry)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn read_record(&self, entry: &IndexEntry) -> Result<Vec<u8>, StorageError> {
+        let mut buf = vec![0u8; entry.len as usize];
+        let mut data = &self.data;
+        data.seek(SeekFrom::Start(entry.offset))?;
+        data.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    pub fn get_by_hash(&self, hash: &Hash256) -> Result<Option<Block>, StorageError> {
+        match self.by_hash.get(hash) {
+            Some(&height) => self.get(height),
+            None => Ok(None),
+        }
+    }
+
+    // Every block, those below `pruned_height` read as headers only
+    pub fn load_all(&self, pruned_height: u64) -> Result<Vec<Block>, StorageError> {
+        let mut blocks = Vec::with_capacity(self.entries.len());
+        for (height, entry) in self.entries.iter().enumerate() {
+            let record = self.read_record(entry)?;
+            if (height as u64) < pruned_height {
+                blocks.push(Block::from_header(serde_json::from_slice(&record)?));
+            } else {
+                blocks.push(serde_json::from_slice(&record)?);
+            }
+        }
+        Ok(blocks)
+    }
+
+    // Rewrite the log with the blocks below `height` cut down to their headers, freeing the
+    // space their bodies took. The new files are written beside the old ones and swapped in
+    // once complete; a swap cut short by a crash is finished the next time the store opens.
+    pub fn compact(&mut self, height: u64) -> Result<(), StorageError> {
+        let height = height.min(self.len());
+        if height <= self.compacted {
+            return Ok(());
+        }
+
+        let data_path = self.dir.join(COMPACT_DATA_FILE);
+        let mut data = BufWriter::new(File::create(&data_path)?);
+        let mut entries = Vec::with_capacity(self.entries.len());
+        let mut index = Vec::with_capacity(self.entries.len() * INDEX_RECORD_LEN);
+        let mut offset = 0;
+        for (position, entry) in self.entries.iter().enumerate() {
+            let mut record = self.read_record(entry)?;
+            if (position as u64) < height {
+                let header: BlockHeader = serde_json::from_slice(&record)?;
+                record = serde_json::to_vec(&Block::from_header(header))?;
+            }
+            data.write_all(&record)?;
+
+            let entry = IndexEntry {
+                offset,
+                len: record.len() as u32,
+                hash: entry.hash,
+            };
+            index.extend_from_slice(&index_record(&entry));
+            offset += record.len() as u64;
+            entries.push(entry);
+        }
+        data.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        write_atomic(self.dir.join(COMPACT_INDEX_FILE), &index)?;
+        finish_compaction(&self.dir)?;
+
+        let open = |name: &str| OpenOptions::new().read(true).write(true).open(self.dir.join(name));
+        self.data = open(DATA_FILE)?;
+        self.index = open(INDEX_FILE)?;
+        self.entries = entries;
+        self.compacted = height;
+        Ok(())
+    }
+
+    // Drop every block at or above `height`
+    pub fn truncate(&mut self, height: u64) -> Result<(), StorageError> {
+        if height >= self.len() {
+            return Ok(());
+        }
+
+        for entry in self.entries.drain(height as usize..) {
+            self.by_hash.remove(&entry.hash);
+        }
+        self.compacted = self.compacted.min(height);
+        self.truncate_files()
+    }
+
+    fn truncate_files(&mut self) -> Result<(), StorageError> {
+        let data_len = self.entries.last().map(|entry| entry.offset + entry.len as u64).unwrap_or(0);
+        let index_len = (self.entries.len() * INDEX_RECORD_LEN) as u64;
+
+        if self.data.metadata()?.len() != data_len {
+            self.data.set_len(data_len)?;
+            self.data.sync_data()?;
+        }
+        if self.index.metadata()?.len() != index_len {
+            self.index.set_len(index_len)?;
+            self.index.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+fn index_record(entry: &IndexEntry) -> Vec<u8> {
+    let mut record = Vec::with_capacity(INDEX_RECORD_LEN);
+    record.extend_from_slice(&entry.offset.to_le_bytes());
+    record.extend_from_slice(&entry.len.to_le_bytes());
+    record.extend_from_slice(entry.hash.to_string().as_bytes());
+    record
+}
+
+// Move a committed compaction's files into place, or drop the log of one that never got
+// as far as writing its index
+fn finish_compaction(dir: &Path) -> io::Result<()> {
+    let data = dir.join(COMPACT_DATA_FILE);
+    let index = dir.join(COMPACT_INDEX_FILE);
+
+    if !index.exists() {
+        if data.exists() {
+            fs::remove_file(&data)?;
+        }
+        return Ok(());
+    }
+    if data.exists() {
+        fs::rename(&data, dir.join(DATA_FILE))?;
+    }
+    fs::rename(&index, dir.join(INDEX_FILE))?;
+    sync_dir(dir)
+}
+
+// Current chain file format. Version 0 was a bare array of blocks, version 1 wrapped the
+// blocks with a checksum, and version 2 added the network, its parameters, the mempool and
+// the pruned state beside the version. Version 3 nests the same contents in a body whose
+// checksum is taken over its bytes exactly as written.
+pub const CHAIN_FILE_VERSION: u32 = 3;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChainFileContents {
+    pub network_id: String,
+    pub params: ChainParams,
+    pub chain: Vec<Block>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mempool: Option<Vec<Transaction>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pruned: Option<PrunedState>,
+}
+
+// Just enough of a chain file to tell its version, read before anything else.
+// Version 1 files are the objects without one.
+#[derive(Deserialize)]
+struct VersionTag {
+    #[serde(default)]
+    version: Option<u32>,
+}
+
+// The body is embedded as is, so its bytes on disk are the ones the checksum covers
+#[derive(Serialize, Deserialize)]
+struct JsonChainFile {
+    version: u32,
+    checksum: String,
+    body: Box<RawValue>,
+}
+
+// The body is a CBOR byte string holding the encoded contents
+#[derive(Serialize, Deserialize)]
+struct BinaryChainFile {
+    version: u32,
+    checksum: String,
+    body: ciborium::value::Value,
+}
+
+#[derive(Deserialize)]
+struct ChainFileV1 {
+    checksum: String,
+    chain: Vec<Block>,
+}
+
+#[derive(Deserialize)]
+struct ChainFileV2 {
+    network_id: String,
+    params: ChainParams,
+    chain: Vec<Block>,
+    #[serde(default)]
+    mempool: Option<Vec<Transaction>>,
+    #[serde(default)]
+    pruned: Option<PrunedState>,
+    checksum: String,
+}
+
+// What a version 2 checksum was taken over: the file without its checksum, re-serialized
+// as JSON with the version first
+#[derive(Serialize)]
+struct ChainFileV2Body<'a> {
+    version: u32,
+    #[serde(flatten)]
+    contents: &'a ChainFileContents,
+}
+
+fn checksum<T: Serialize + ?Sized>(value: &T) -> Result<String, StorageError> {
+    Ok(checksum_bytes(&serde_json::to_vec(value)?))
+}
+
+fn checksum_bytes(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainFileFormat {
+    Json,
+    // CBOR encoding of the same structure, for smaller files and faster parsing
+    Binary,
+}
+
+impl std::str::FromStr for ChainFileFormat {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(ChainFileFormat::Json),
+            "binary" | "bin" | "cbor" => Ok(ChainFileFormat::Binary),
+            other => Err(ConfigError::invalid("chain file format", other, "expected json or binary")),
+        }
+    }
+}
+
+impl std::fmt::Display for ChainFileFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChainFileFormat::Json => write!(f, "json"),
+            ChainFileFormat::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+impl ChainFileFormat {
+    // JSON files always start with an object or array; anything else is taken as CBOR
+    fn detect(data: &[u8]) -> Self {
+        match data.iter().find(|byte| !byte.is_ascii_whitespace()) {
+            Some(b'{') | Some(b'[') => ChainFileFormat::Json,
+            _ => ChainFileFormat::Binary,
+        }
+    }
+
+    // Whether the file is an array rather than an object, which only version 0 files are
+    fn is_array(self, data: &[u8]) -> bool {
+        match self {
+            ChainFileFormat::Json => data.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b'['),
+            // CBOR major type 4
+            ChainFileFormat::Binary => data.first().map(|byte| byte >> 5 == 4).unwrap_or(false),
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(self, data: &[u8]) -> Result<T, StorageError> {
+        match self {
+            ChainFileFormat::Json => Ok(serde_json::from_slice(data)?),
+            ChainFileFormat::Binary => ciborium::de::from_reader(data).map_err(binary_error),
+        }
+    }
+}
+
+fn binary_error<E: std::fmt::Display>(e: E) -> StorageError {
+    StorageError::Binary(e.to_string())
+}
+
+// The checksum is taken over the body as encoded in the file, whichever encoding that is
+pub fn write_chain_file<P: AsRef<Path>>(path: P, contents: ChainFileContents, format: ChainFileFormat) -> Result<(), StorageError> {
+    let encoded = match format {
+        ChainFileFormat::Json => {
+            let body = serde_json::to_string(&contents)?;
+            let file = JsonChainFile {
+                version: CHAIN_FILE_VERSION,
+                checksum: checksum_bytes(body.as_bytes()),
+                body: RawValue::from_string(body)?,
+            };
+            serde_json::to_vec(&file)?
+        }
+        ChainFileFormat::Binary => {
+            let mut body = Vec::new();
+            ciborium::ser::into_writer(&contents, &mut body).map_err(binary_error)?;
+            let file = BinaryChainFile {
+                version: CHAIN_FILE_VERSION,
+                checksum: checksum_bytes(&body),
+                body: ciborium::value::Value::Bytes(body),
+            };
+            let mut buf = Vec::new();
+            ciborium::ser::into_writer(&file, &mut buf).map_err(binary_error)?;
+            buf
+        }
+    };
+    Ok(write_atomic(path, &encoded)?)
+}
+
+// Read a chain file of any known version and either format, migrating older ones to the current layout.
+// Older versions take `network_id` and `params` from the caller since they never stored them.
+pub fn read_chain_file<P: AsRef<Path>>(path: P, network_id: &str, params: &ChainParams) -> Result<ChainFileContents, StorageError> {
+    let data = fs::read(path)?;
+    let format = ChainFileFormat::detect(&data);
+
+    let legacy = |chain| ChainFileContents {
+        network_id: network_id.to_string(),
+        params: params.clone(),
+        chain,
+        mempool: None,
+        pruned: None,
+    };
+
+    if format.is_array(&data) {
+        return Ok(legacy(format.decode(&data)?));
+    }
+
+    match format.decode::<VersionTag>(&data)?.version.unwrap_or(1) {
+        1 => {
+            let file: ChainFileV1 = format.decode(&data)?;
+            if checksum(&file.chain)? != file.checksum {
+                return Err(StorageError::ChecksumMismatch("Chain file"));
+            }
+            Ok(legacy(file.chain))
+        }
+        2 => {
+            let file: ChainFileV2 = format.decode(&data)?;
+            let contents = ChainFileContents {
+                network_id: file.network_id,
+                params: file.params,
+                chain: file.chain,
+                mempool: file.mempool,
+                pruned: file.pruned,
+            };
+            if checksum(&ChainFileV2Body { version: 2, contents: &contents })? != file.checksum {
+                return Err(StorageError::ChecksumMismatch("Chain file"));
+            }
+            Ok(contents)
+        }
+        CHAIN_FILE_VERSION => {
+            let (checksum, body) = match format {
+                ChainFileFormat::Json => {
+                    let file: JsonChainFile = format.decode(&data)?;
+                    (file.checksum, file.body.get().as_bytes().to_vec())
+                }
+                ChainFileFormat::Binary => {
+                    let file: BinaryChainFile = format.decode(&data)?;
+                    match file.body {
+                        ciborium::value::Value::Bytes(body) => (file.checksum, body),
+                        _ => return Err(StorageError::Binary("chain file body is not a byte string".to_string())),
+                    }
+                }
+            };
+            if checksum_bytes(&body) != checksum {
+                return Err(StorageError::ChecksumMismatch("Chain file"));
+            }
+            format.decode(&body)
+        }
+        version => Err(StorageError::UnsupportedVersion(version)),
+    }
+}
+
+// Node state kept next to the block store in a data directory
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NodeState {
+    pub network_id: String,
+    pub params: ChainParams,
+    pub mempool: Vec<Transaction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pruned: Option<PrunedState>,
+    // Height the block store was last compacted to, so a restart does not rewrite it again
+    #[serde(default)]
+    pub compacted: u64,
+}
+
+pub fn read_node_state<P: AsRef<Path>>(dir: P) -> Result<Option<NodeState>, StorageError> {
+    match fs::read(dir.as_ref().join(STATE_FILE)) {
+        Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn write_node_state<P: AsRef<Path>>(dir: P, state: &NodeState) -> Result<(), StorageError> {
+    Ok(write_atomic(dir.as_ref().join(STATE_FILE), &serde_json::to_vec(state)?)?)
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotFile {
+    snapshot: Snapshot,
+    checksum: String,
+}
+
+pub fn write_snapshot_file<P: AsRef<Path>>(path: P, snapshot: &Snapshot) -> Result<(), StorageError> {
+    let file = SnapshotFile {
+        checksum: checksum(snapshot)?,
+        snapshot: snapshot.clone(),
+    };
+    Ok(write_atomic(path, &serde_json::to_vec(&file)?)?)
+}
+
+pub fn read_snapshot_file<P: AsRef<Path>>(path: P) -> Result<Snapshot, StorageError> {
+    let file: SnapshotFile = serde_json::from_slice(&fs::read(path)?)?;
+    if checksum(&file.snapshot)? != file.checksum {
+        return Err(StorageError::ChecksumMismatch("Snapshot file"));
+    }
+    Ok(file.snapshot)
+}
+
+// Replace `path` without ever exposing a partially written file: write a sibling temp file,
+// fsync it, rename it over the target and fsync the directory so the rename itself is durable
+pub fn write_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    {
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(contents)?;
+        tmp.sync_all()?;
+    }
+
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => sync_dir(parent),
+        _ => sync_dir(Path::new(".")),
+    }
+}
+
+// Make renames in `dir` durable
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
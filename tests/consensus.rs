@@ -0,0 +1,186 @@
+// Proof-of-stake chains driven from the library: proposers, staking and slashing.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use blockchain::consensus::{self, ConsensusConfig, DoubleSignEvidence, ProofOfStake, SLOT_SECONDS};
+use blockchain::{AccountState, Block, Blockchain, ChainParams, Hash256, ManualClock, TxKind, DEFAULT_NETWORK_ID};
+use libp2p::identity::ed25519::Keypair;
+
+const GENESIS_TIME: u64 = 1_700_000_000;
+
+fn pos_params(validators: &[Keypair]) -> ChainParams {
+    let mut params = ChainParams::new(0, 50.0).unwrap();
+    params.genesis_timestamp = Some(GENESIS_TIME);
+    params.consensus = ConsensusConfig::ProofOfStake {
+        genesis_validators: validators.iter().map(consensus::public_key_hex).collect(),
+    };
+    params
+}
+
+fn pos_chain(validators: &[Keypair]) -> Blockchain {
+    pos_chain_on(validators, Arc::new(ManualClock::new(GENESIS_TIME)))
+}
+
+fn pos_chain_on(validators: &[Keypair], clock: Arc<ManualClock>) -> Blockchain {
+    Blockchain::with_clock(DEFAULT_NETWORK_ID, pos_params(validators), clock)
+}
+
+// Seal a block paying `address` with whichever of `keys` is scheduled to propose it
+fn seal_as_leader(chain: &mut Blockchain, keys: &[Keypair], address: &str) -> Block {
+    for key in keys {
+        chain.set_signer(key.clone());
+        if let Ok(block) = chain.block_template(address).seal() {
+            return block;
+        }
+    }
+    panic!("None of the keys may propose block {}", chain.get_latest_block().index + 1);
+}
+
+fn mine_as_leader(chain: &mut Blockchain, keys: &[Keypair], address: &str) -> Block {
+    let block = seal_as_leader(chain, keys, address);
+    chain.accept_block(block.clone()).unwrap();
+    block
+}
+
+#[test]
+fn reported_evidence_survives_a_peer_block_landing_first() {
+    let keys = [Keypair::generate(), Keypair::generate()];
+    let mut chain = pos_chain(&keys);
+    let mut peer = pos_chain(&keys);
+
+    // The proposer of block 1 signs two different blocks at that height
+    let first = mine_as_leader(&mut chain, &keys, "alice");
+    let second = seal_as_leader(&mut peer, &keys, "bob");
+    let genesis = chain.chain[0].clone();
+    assert!(!chain.try_replace_chain(vec![genesis, second]).unwrap());
+    assert_eq!(chain.pending_transactions.len(), 1);
+    assert!(matches!(chain.pending_transactions[0].kind, TxKind::Evidence(_)));
+
+    // A peer's block without the evidence takes the next height first
+    peer.submit_block(first).unwrap();
+    let block = mine_as_leader(&mut peer, &keys, "carol");
+    chain.submit_block(block).unwrap();
+
+    // The evidence still goes into our next block and leaves the mempool
+    let block = mine_as_leader(&mut chain, &keys, "dave");
+    assert!(block.transactions.iter().any(|transaction| matches!(transaction.kind, TxKind::Evidence(_))));
+    assert!(chain.pending_transactions.is_empty());
+}
+
+#[test]
+fn stake_needs_a_validator_key() {
+    let keys = [Keypair::generate()];
+    let mut chain = pos_chain(&keys);
+    mine_as_leader(&mut chain, &keys, "alice");
+
+    let error = chain.add_stake("alice".to_string(), "not-a-key".to_string(), 10.0).unwrap_err();
+    assert_eq!(error.to_string(), "Cannot stake with not-a-key, which is not a validator public key");
+    assert!(chain.pending_transactions.is_empty());
+
+    let validator = consensus::public_key_hex(&Keypair::generate());
+    chain.add_stake("alice".to_string(), validator, 10.0).unwrap();
+}
+
+#[test]
+fn leadership_moves_on_when_a_validator_is_offline() {
+    let keys = [Keypair::generate(), Keypair::generate()];
+    let online = &keys[..1];
+    let clock = Arc::new(ManualClock::new(GENESIS_TIME));
+    let mut chain = pos_chain_on(&keys, clock.clone());
+    chain.set_signer(online[0].clone());
+
+    // Whenever the offline validator leads, the next slot draws again
+    let mut waited = 0;
+    while chain.chain.len() <= 10 {
+        match chain.block_template("alice").seal() {
+            Ok(block) => chain.accept_block(block).unwrap(),
+            Err(_) => {
+                clock.advance(SLOT_SECONDS);
+                waited += 1;
+            }
+        }
+        assert!(waited < 100, "Leadership never came back to the online validator");
+    }
+
+    let online_key = consensus::public_key_hex(&online[0]);
+    assert!(chain.chain[1..].iter().all(|block| block.signature.as_ref().unwrap().signer == online_key));
+    assert!(chain.validate_chain().is_ok());
+
+    // A node that sees the same time accepts the chain
+    let mut peer = pos_chain_on(&keys, clock);
+    assert!(peer.try_replace_chain(chain.chain.clone()).unwrap());
+}
+
+#[test]
+fn blocks_cannot_claim_a_slot_before_it_starts() {
+    let keys = [Keypair::generate(), Keypair::generate()];
+    let mut chain = pos_chain(&keys);
+
+    // A proposer whose clock runs ahead seals into slots that have not started here
+    let ahead = Arc::new(ManualClock::new(GENESIS_TIME + 10 * SLOT_SECONDS));
+    let mut proposer = pos_chain_on(&keys, ahead);
+    let block = seal_as_leader(&mut proposer, &keys, "alice");
+
+    let error = chain.accept_block(block).unwrap_err();
+    assert!(error.to_string().contains("too far in the future"), "{}", error);
+}
+
+#[test]
+fn leaders_are_drawn_by_stake() {
+    let [a, b, c] = [(); 3].map(|_| consensus::public_key_hex(&Keypair::generate()));
+    let engine = ProofOfStake::new(vec![a.clone(), b.clone()]);
+    let hash = Hash256::ZERO;
+    let leaders = |state: &AccountState| (1..200).map(|height| engine.leader(height, &hash, 0, state).unwrap()).collect::<Vec<_>>();
+
+    // Until anything is staked the genesis validators take turns
+    let mut state = AccountState::default();
+    assert_eq!(leaders(&state).into_iter().collect::<HashSet<_>>(), HashSet::from([a.clone(), b.clone()]));
+    assert_eq!(engine.leader(7, &hash, 0, &state), engine.leader(7, &hash, 0, &state));
+
+    // Then only validators with stake, in proportion to it
+    state.stakes.insert(a.clone(), 1.0);
+    state.stakes.insert(c.clone(), 99.0);
+    let drawn = leaders(&state);
+    assert!(!drawn.contains(&b));
+    assert!(drawn.iter().filter(|leader| **leader == c).count() > 180);
+
+    // Slashed validators never again
+    state.slashed.insert(c.clone());
+    assert!(leaders(&state).iter().all(|leader| *leader == a));
+    state.slashed.insert(a);
+    state.slashed.insert(b);
+    assert_eq!(engine.leader(1, &hash, 0, &state), None);
+}
+
+#[test]
+fn double_signer_loses_its_stake_and_its_turns() {
+    let keys = [Keypair::generate(), Keypair::generate()];
+    let validators: Vec<String> = keys.iter().map(consensus::public_key_hex).collect();
+    let mut chain = pos_chain(&keys);
+    let mut peer = pos_chain(&keys);
+
+    let block = mine_as_leader(&mut chain, &keys, "alice");
+    peer.submit_block(block).unwrap();
+    chain.add_stake("alice".to_string(), validators[0].clone(), 30.0).unwrap();
+    chain.add_stake("alice".to_string(), validators[1].clone(), 10.0).unwrap();
+    let block = mine_as_leader(&mut chain, &keys, "alice");
+    peer.submit_block(block).unwrap();
+
+    // The leader of block 3 signs two different blocks, and someone reports them
+    let first = mine_as_leader(&mut chain, &keys, "alice");
+    let second = seal_as_leader(&mut peer, &keys, "bob");
+    let offender = first.signature.as_ref().unwrap().signer.clone();
+    let honest = validators.iter().find(|validator| **validator != offender).unwrap().clone();
+    let evidence = DoubleSignEvidence::from_headers(first.header(), second.header()).unwrap();
+    chain.submit_evidence("reporter".to_string(), evidence).unwrap();
+    mine_as_leader(&mut chain, &keys, "alice");
+
+    assert_eq!(chain.get_stake(&offender), 0.0);
+    assert!(chain.get_stake(&honest) > 0.0);
+    assert!(chain.state_at(4).unwrap().slashed.contains(&offender));
+    for _ in 0..10 {
+        let block = mine_as_leader(&mut chain, &keys, "alice");
+        assert_eq!(block.signature.unwrap().signer, honest);
+    }
+}
@@ -0,0 +1,43 @@
+// Events a chain emits as blocks connect and branches switch.
+
+use std::sync::Arc;
+
+use blockchain::{Block, Blockchain, ChainParams, Event, ManualClock, DEFAULT_NETWORK_ID};
+
+fn chain_at(clock: Arc<ManualClock>) -> Blockchain {
+    let mut chain = Blockchain::with_clock(DEFAULT_NETWORK_ID, ChainParams::new(1, 50.0).unwrap(), clock);
+    chain.mining_threads = 1;
+    chain
+}
+
+#[test]
+fn chain_replacement_lists_both_branches() {
+    let clock = Arc::new(ManualClock::new(1_700_000_000));
+    let mut chain = chain_at(clock.clone());
+    let mut peer = chain_at(clock);
+
+    chain.mine_pending_transactions("miner").unwrap();
+    chain.add_transaction("miner".to_string(), "alice".to_string(), 5.0).unwrap();
+    chain.mine_pending_transactions("miner").unwrap();
+    for _ in 0..3 {
+        peer.mine_pending_transactions("bob").unwrap();
+    }
+
+    let mut events = chain.events().subscribe();
+    let old = chain.chain[1..].to_vec();
+    assert!(chain.try_replace_chain(peer.chain.clone()).unwrap());
+
+    let mut replaced = None;
+    while let Ok(event) = events.try_recv() {
+        if let Event::ChainReplaced { common, disconnected, connected, tip } = event {
+            replaced = Some((common, disconnected, connected, tip));
+        }
+    }
+    let (common, disconnected, connected, tip) = replaced.expect("No ChainReplaced event");
+    assert_eq!(common, 1);
+    let hashes = |blocks: &[Block]| blocks.iter().map(|block| block.hash).collect::<Vec<_>>();
+    assert_eq!(hashes(&disconnected), hashes(&old));
+    assert_eq!(hashes(&connected), hashes(&peer.chain[1..]));
+    assert_eq!(tip.hash, peer.get_latest_block().hash);
+    assert!(connected.iter().all(|block| !block.transactions.is_empty()));
+}
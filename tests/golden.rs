@@ -0,0 +1,91 @@
+// Golden tests: chains built on a ManualClock and mined on one thread come out hash for
+// hash the same on every run, so any change to block or transaction encoding, hashing or
+// mining order shows up here.
+
+use std::sync::Arc;
+
+use blockchain::algo::Game;
+use blockchain::{Blockchain, ChainParams, ManualClock, DEFAULT_NETWORK_ID};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sha2::{Digest, Sha256};
+
+fn golden_chain() -> Blockchain {
+    let clock = Arc::new(ManualClock::new(1_700_000_000));
+    let mut chain = Blockchain::with_clock(DEFAULT_NETWORK_ID, ChainParams::new(2, 50.0).unwrap(), clock.clone());
+    chain.mining_threads = 1;
+
+    chain.mine_pending_transactions("miner").unwrap();
+    clock.advance(60);
+    chain.add_transaction("miner".to_string(), "alice".to_string(), 20.0).unwrap();
+    chain.add_transaction("miner".to_string(), "bob".to_string(), 5.0).unwrap();
+    chain.mine_pending_transactions("miner").unwrap();
+    clock.advance(60);
+    chain.add_transaction("alice".to_string(), "bob".to_string(), 7.5).unwrap();
+    chain.mine_pending_transactions("alice").unwrap();
+    chain
+}
+
+#[test]
+fn golden_chain_hashes() {
+    let chain = golden_chain();
+    let hashes: Vec<String> = chain.chain.iter().map(|block| block.hash.to_string()).collect();
+
+    assert_eq!(hashes, [
+        "ec609ddd2ed215a2ac4bd247771b355226a2009ef352b39428abe091230a494f",
+        "00d0edd19b176e7b23e89941fd3d62da213fb0678f378fcb284f11e928b86aa8",
+        "0095c0b540b8c162dacb74f41b3e15764ee8d39f68d1617d24aec80dc32f1bd3",
+        "00630276b14217a491d095abd24ff7828f97e96f2ac35aa6eb585f80368a8424",
+    ]);
+    assert!(chain.validate_chain().is_ok());
+    assert_eq!(chain.get_balance("miner"), 75.0);
+    assert_eq!(chain.get_balance("alice"), 62.5);
+    assert_eq!(chain.get_balance("bob"), 12.5);
+}
+
+#[test]
+fn golden_chain_is_reproducible() {
+    let first: Vec<_> = golden_chain().chain.iter().map(|block| block.hash).collect();
+    let second: Vec<_> = golden_chain().chain.iter().map(|block| block.hash).collect();
+    assert_eq!(first, second);
+}
+
+// The secret a game seeded with 7 holds, drawn the way the game draws it: a digit count,
+// a sign, a leading digit that is not zero and then the rest of the digits
+fn seed_7_secret() -> String {
+    let mut rng = StdRng::seed_from_u64(7);
+    let digits: usize = rng.gen_range(0..=1_000_000);
+    let mut secret = String::with_capacity(digits + 1);
+    if rng.gen_bool(0.5) {
+        secret.push('-');
+    }
+    secret.push(char::from(b'0' + rng.gen_range(1..=9) as u8));
+    for _ in 1..digits {
+        secret.push(char::from(b'0' + rng.gen_range(0..=9) as u8));
+    }
+    secret
+}
+
+#[test]
+fn seeded_game_replays() {
+    let secret = seed_7_secret();
+    assert_eq!(secret.len(), 30_318);
+    assert!(secret.starts_with("-11656797217560369561"));
+    assert_eq!(
+        format!("{:x}", Sha256::digest(secret.as_bytes())),
+        "a67a2a0cedbee35be6154d77cb4e062f69fca9270ac39b9371b1c3e6d8276975",
+    );
+    assert_eq!(Game::with_rng(StdRng::seed_from_u64(7)).check(&secret), Ok(()));
+
+    // Wrong guesses get an honest hint one time in ten thousand, on the same turns every run
+    let mut game = Game::with_rng(StdRng::seed_from_u64(7));
+    let hints: Vec<(usize, String)> = (0..40_000)
+        .filter_map(|turn| match game.check(if turn % 2 == 0 { "1" } else { "-1" }) {
+            Err(hint) if hint != "NaN" => Some((turn, hint)),
+            _ => None,
+        })
+        .collect();
+    let expected = [(26_530, "Too Big"), (30_595, "Too Big"), (37_572, "Too Big")];
+    assert_eq!(hints, expected.map(|(turn, hint)| (turn, hint.to_string())));
+    assert_eq!(game.check("abc"), Err("Error".to_string()));
+}
@@ -0,0 +1,33 @@
+// Ledger rules seen from the library: balances, stakes and the history they leave behind.
+
+use blockchain::blockchain::Direction;
+use blockchain::consensus;
+use blockchain::{Blockchain, ChainParams, DEFAULT_NETWORK_ID};
+use libp2p::identity::ed25519::Keypair;
+
+fn funded_chain() -> Blockchain {
+    let mut chain = Blockchain::new(DEFAULT_NETWORK_ID, ChainParams::new(1, 50.0).unwrap());
+    chain.mining_threads = 1;
+    chain.mine_pending_transactions("alice").unwrap();
+    chain
+}
+
+#[test]
+fn history_labels_stake_without_counting_it_as_received() {
+    let mut chain = funded_chain();
+    let validator = consensus::public_key_hex(&Keypair::generate());
+    chain.add_stake("alice".to_string(), validator.clone(), 10.0).unwrap();
+    chain.add_transaction("alice".to_string(), "bob".to_string(), 5.0).unwrap();
+    chain.mine_pending_transactions("miner").unwrap();
+
+    let alice: Vec<_> = chain.get_history("alice", 0, 10).entries.iter().map(|entry| (entry.direction, entry.balance)).collect();
+    assert_eq!(alice, [(Direction::Received, 50.0), (Direction::Staked, 40.0), (Direction::Sent, 35.0)]);
+
+    let history = chain.get_history(&validator, 0, 10);
+    assert_eq!(history.total, 1);
+    assert_eq!(history.entries[0].direction, Direction::StakeReceived);
+    assert_eq!(history.entries[0].counterparty, "alice");
+    assert_eq!(history.entries[0].balance, 0.0);
+    assert_eq!(chain.get_balance(&validator), 0.0);
+    assert_eq!(chain.get_stake(&validator), 10.0);
+}
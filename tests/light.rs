@@ -0,0 +1,126 @@
+// A light client following header chains and proofs served by a full chain.
+
+use std::sync::Arc;
+
+use blockchain::error::ValidationError;
+use blockchain::{BlockHeader, Blockchain, ChainParams, LightClient, ManualClock, DEFAULT_NETWORK_ID};
+
+const GENESIS_TIME: u64 = 1_700_000_000;
+
+fn params() -> ChainParams {
+    let mut params = ChainParams::new(2, 50.0).unwrap();
+    params.genesis_timestamp = Some(GENESIS_TIME);
+    params
+}
+
+fn full_chain(blocks: usize) -> Blockchain {
+    let clock = Arc::new(ManualClock::new(GENESIS_TIME));
+    let mut chain = Blockchain::with_clock(DEFAULT_NETWORK_ID, params(), clock.clone());
+    chain.mining_threads = 1;
+    for _ in 0..blocks {
+        clock.advance(60);
+        chain.mine_pending_transactions("miner").unwrap();
+    }
+    chain
+}
+
+// Search nonces until the header's own hash does or does not meet difficulty 2
+fn reseal(header: &mut BlockHeader, enough_work: bool) {
+    loop {
+        header.nonce += 1;
+        header.hash = header.calculate_hash();
+        if header.hash.to_string().starts_with("00") == enough_work {
+            return;
+        }
+    }
+}
+
+fn light_client(params: ChainParams) -> LightClient {
+    LightClient::new(DEFAULT_NETWORK_ID, params, ["alice".to_string()])
+}
+
+#[test]
+fn follows_the_full_chain_headers() {
+    let chain = full_chain(4);
+    let mut client = light_client(params());
+
+    assert!(client.add_headers(chain.headers_from(0, 3)).unwrap());
+    assert!(client.add_headers(chain.headers_from(1, 10)).unwrap());
+    assert_eq!(client.tip().unwrap().hash, chain.get_latest_block().hash);
+
+    // The same headers again add no weight
+    assert!(!client.add_headers(chain.headers_from(0, 10)).unwrap());
+}
+
+#[test]
+fn rejects_headers_that_break_the_chain() {
+    let chain = full_chain(4);
+    let mut client = light_client(params());
+    client.add_headers(chain.headers_from(0, 2)).unwrap();
+
+    // A gap above the tip
+    let error = client.add_headers(chain.headers_from(3, 10)).unwrap_err();
+    assert!(matches!(error, ValidationError::HeightOutOfSequence { height: 3 }));
+
+    // A header whose parent is not the one before it
+    let mut headers = chain.headers_from(2, 10);
+    headers[0].previous_hash = headers[1].hash;
+    reseal(&mut headers[0], true);
+    let error = client.add_headers(headers).unwrap_err();
+    assert!(matches!(error, ValidationError::BrokenLink { height: 2 }));
+
+    // A header whose hash is not its own
+    let mut headers = chain.headers_from(2, 10);
+    headers[0].nonce += 1;
+    let error = client.add_headers(headers).unwrap_err();
+    assert!(matches!(error, ValidationError::InvalidHash { height: 2 }));
+    assert_eq!(client.tip().unwrap().index, 1);
+}
+
+#[test]
+fn rejects_headers_without_enough_work() {
+    let chain = full_chain(2);
+    let mut client = light_client(params());
+
+    let mut headers = chain.headers_from(0, 10);
+    reseal(&mut headers[2], false);
+    let error = client.add_headers(headers).unwrap_err();
+    assert!(matches!(error, ValidationError::InvalidSeal { .. }));
+}
+
+#[test]
+fn rejects_headers_against_a_checkpoint() {
+    let chain = full_chain(3);
+    let other = {
+        let mut other = full_chain(1);
+        other.add_transaction("miner".to_string(), "bob".to_string(), 1.0).unwrap();
+        other.mine_pending_transactions("miner").unwrap();
+        other
+    };
+    let mut params = params();
+    params.checkpoints.insert(2, chain.get_block(2).unwrap().hash);
+    let mut client = light_client(params);
+
+    let error = client.add_headers(other.headers_from(0, 10)).unwrap_err();
+    assert!(matches!(error, ValidationError::CheckpointConflict { height: 2, .. }));
+    assert!(client.add_headers(chain.headers_from(0, 10)).unwrap());
+}
+
+#[test]
+fn takes_proofs_only_against_its_headers() {
+    let mut chain = full_chain(1);
+    chain.add_transaction("miner".to_string(), "alice".to_string(), 7.5).unwrap();
+    chain.mine_pending_transactions("miner").unwrap();
+    let mut client = light_client(params());
+    client.add_headers(chain.headers_from(0, 10)).unwrap();
+
+    let proof = chain.prove_transactions("alice").pop().unwrap();
+    let mut forged = proof.clone();
+    forged.transaction.amount = 75.0;
+    let error = client.add_proof(forged).unwrap_err();
+    assert!(matches!(error, ValidationError::InvalidProof { .. }));
+
+    assert!(client.add_proof(proof.clone()).unwrap());
+    assert_eq!(client.balance("alice"), 7.5);
+    assert_eq!(client.confirmations(&proof), 1);
+}
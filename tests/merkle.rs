@@ -0,0 +1,67 @@
+// Merkle roots and proofs over transaction ids, on their own and as blocks commit to them.
+
+use blockchain::merkle::{merkle_proof, merkle_root};
+use blockchain::{Blockchain, ChainParams, Hash256, DEFAULT_NETWORK_ID};
+use sha2::{Digest, Sha256};
+
+fn leaves(count: u8) -> Vec<Hash256> {
+    (0..count).map(|i| Hash256::from(<[u8; 32]>::from(Sha256::digest([i])))).collect()
+}
+
+#[test]
+fn every_leaf_proves_against_the_root() {
+    for count in 1..=9 {
+        let leaves = leaves(count);
+        let root = merkle_root(&leaves);
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = merkle_proof(&leaves, index).unwrap();
+            assert!(proof.verify(leaf, &root), "Leaf {} of {}", index, count);
+        }
+        assert!(merkle_proof(&leaves, leaves.len()).is_none());
+    }
+}
+
+#[test]
+fn proofs_fail_for_other_leaves_siblings_and_positions() {
+    let leaves = leaves(5);
+    let root = merkle_root(&leaves);
+    let proof = merkle_proof(&leaves, 2).unwrap();
+
+    assert!(!proof.verify(&leaves[3], &root));
+
+    let mut tampered = proof.clone();
+    tampered.siblings[1] = leaves[0];
+    assert!(!tampered.verify(&leaves[2], &root));
+
+    // A position the siblings cannot reach would otherwise alias a real one
+    let mut beyond = proof;
+    beyond.index += 1 << beyond.siblings.len();
+    assert!(!beyond.verify(&leaves[2], &root));
+}
+
+#[test]
+fn empty_and_single_trees() {
+    assert_eq!(merkle_root(&[]), Hash256::ZERO);
+    let leaves = leaves(1);
+    assert_eq!(merkle_root(&leaves), leaves[0]);
+    assert!(merkle_proof(&leaves, 0).unwrap().siblings.is_empty());
+}
+
+#[test]
+fn chain_proofs_match_block_transaction_roots() {
+    let mut chain = Blockchain::new(DEFAULT_NETWORK_ID, ChainParams::new(1, 50.0).unwrap());
+    chain.mining_threads = 1;
+    chain.mine_pending_transactions("miner").unwrap();
+    for amount in [1.0, 2.0, 3.0] {
+        chain.add_transaction("miner".to_string(), "alice".to_string(), amount).unwrap();
+    }
+    chain.mine_pending_transactions("miner").unwrap();
+
+    let proofs = chain.prove_transactions("alice");
+    assert_eq!(proofs.len(), 3);
+    for proof in proofs {
+        let block = chain.get_block(proof.height).unwrap();
+        assert_eq!(block.hash, proof.block_hash);
+        assert!(proof.proof.verify(&proof.transaction.id(), &block.tx_root));
+    }
+}
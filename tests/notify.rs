@@ -0,0 +1,45 @@
+// Notify hooks run against a chain's own event bus.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use blockchain::{Blockchain, ChainParams, ManualClock, Notifier, NotifyConfig, DEFAULT_NETWORK_ID};
+
+fn chain_at(clock: Arc<ManualClock>) -> Blockchain {
+    let mut chain = Blockchain::with_clock(DEFAULT_NETWORK_ID, ChainParams::new(1, 50.0).unwrap(), clock);
+    chain.mining_threads = 1;
+    chain
+}
+
+#[tokio::test]
+async fn wallet_command_runs_for_transfers_a_new_branch_confirms() {
+    let clock = Arc::new(ManualClock::new(1_700_000_000));
+    let mut chain = chain_at(clock.clone());
+    let mut peer = chain_at(clock);
+
+    // The peer's branch pays alice twice, in blocks 2 and 3
+    let mut paid = Vec::new();
+    peer.mine_pending_transactions("miner").unwrap();
+    for height in 2..=3 {
+        paid.push(format!("{} {}", peer.add_transaction("miner".to_string(), "alice".to_string(), 5.0).unwrap(), height));
+        peer.mine_pending_transactions("miner").unwrap();
+    }
+    chain.mine_pending_transactions("bob").unwrap();
+
+    let log = std::env::temp_dir().join(format!("rustchain-notify-{}", std::process::id()));
+    let _ = std::fs::remove_file(&log);
+    let config = NotifyConfig {
+        wallet_command: Some(format!("echo '%s %h' >> {}", log.display())),
+        watched_addresses: HashSet::from(["alice".to_string()]),
+        ..NotifyConfig::default()
+    };
+    let notifier = Notifier::spawn(config, chain.events().subscribe());
+    assert!(chain.try_replace_chain(peer.chain.clone()).unwrap());
+    notifier.finish().await;
+
+    let mut lines: Vec<_> = std::fs::read_to_string(&log).unwrap().lines().map(str::to_string).collect();
+    lines.sort();
+    paid.sort();
+    assert_eq!(lines, paid);
+    let _ = std::fs::remove_file(&log);
+}
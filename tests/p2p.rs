@@ -0,0 +1,107 @@
+// Nodes syncing over localhost: chains, new blocks and headers bigger than floodsub carries.
+
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+use blockchain::{
+    Blockchain, BlockchainHandle, ChainParams, LightClient, LightNode, ManualClock, Transaction, P2P, DEFAULT_NETWORK_ID,
+};
+
+// Each test builds its chains on its own genesis, so nodes of other tests that mDNS turns
+// up cannot switch them to another chain
+fn params(genesis: u64) -> ChainParams {
+    let mut params = ChainParams::new(0, 50.0).unwrap();
+    params.genesis_timestamp = Some(genesis);
+    params
+}
+
+fn chain_at(genesis: u64) -> Blockchain {
+    let clock = Arc::new(ManualClock::new(genesis));
+    let mut chain = Blockchain::with_clock(DEFAULT_NETWORK_ID, params(genesis), clock);
+    chain.mining_threads = 1;
+    chain
+}
+
+fn long_chain(genesis: u64, length: u64) -> Blockchain {
+    let mut chain = chain_at(genesis);
+    for _ in 0..length {
+        chain.mine_pending_transactions("miner").unwrap();
+    }
+    chain
+}
+
+// A localhost address nothing is listening on
+fn free_address() -> String {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    format!("/ip4/127.0.0.1/tcp/{}", port)
+}
+
+async fn spawn_node(chain: Blockchain, peer: Option<&str>) -> (BlockchainHandle, String) {
+    let handle = BlockchainHandle::spawn(chain);
+    let address = free_address();
+    let mut p2p = P2P::new(handle.clone()).await.unwrap();
+    p2p.start(&address).await.unwrap();
+    if let Some(peer) = peer {
+        p2p.connect(peer).await.unwrap();
+    }
+    tokio::spawn(async move { p2p.run().await });
+    (handle, address)
+}
+
+async fn wait_for_height(handle: &BlockchainHandle, height: u64) {
+    for _ in 0..600 {
+        if handle.tip().await.height >= height {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Node stuck at height {}, expected {}", handle.tip().await.height, height);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn node_syncs_a_long_chain_and_large_blocks_from_a_peer() {
+    let genesis = 1_700_000_000;
+    let (source, address) = spawn_node(long_chain(genesis, 1200), None).await;
+    let (node, _) = spawn_node(chain_at(genesis), Some(&address)).await;
+
+    wait_for_height(&node, 1200).await;
+    assert_eq!(node.tip().await.hash, source.tip().await.hash);
+
+    // A block far bigger than a floodsub message still reaches the peer
+    for i in 0..40 {
+        source.add_transaction(Transaction::new("miner".to_string(), format!("payee-{}", i), 1.0)).await.unwrap();
+    }
+    let block = source.mine("miner").await.unwrap();
+    assert!(serde_json::to_vec(&block).unwrap().len() > 2048);
+    wait_for_height(&node, 1201).await;
+    assert_eq!(node.tip().await.hash, block.hash);
+    assert_eq!(node.balance("payee-39").await.balance, 1.0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn light_node_syncs_more_headers_than_one_response_holds() {
+    let genesis = 1_700_100_000;
+    let mut chain = long_chain(genesis, 2050);
+    chain.add_transaction("miner".to_string(), "alice".to_string(), 5.0).unwrap();
+    for _ in 0..50 {
+        chain.mine_pending_transactions("miner").unwrap();
+    }
+    let tip = chain.get_latest_block().hash;
+    let (_source, address) = spawn_node(chain, None).await;
+
+    let client = LightClient::new(DEFAULT_NETWORK_ID, params(genesis), ["alice".to_string()]);
+    let mut light = LightNode::new(client).await.unwrap();
+    light.start(&free_address()).await.unwrap();
+    light.connect(&address).await.unwrap();
+
+    // The node runs in short bursts so its client can be looked at in between
+    for _ in 0..600 {
+        let _ = tokio::time::timeout(Duration::from_millis(100), light.run()).await;
+        if light.client().tip().map(|header| header.hash) == Some(tip) && !light.client().proven_transactions().is_empty() {
+            break;
+        }
+    }
+    assert_eq!(light.client().tip().map(|header| header.index), Some(2100));
+    assert_eq!(light.client().balance("alice"), 5.0);
+}
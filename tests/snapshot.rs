@@ -0,0 +1,60 @@
+// Nodes started from a snapshot of the account state instead of replaying history.
+
+use blockchain::{Blockchain, ChainParams, DEFAULT_NETWORK_ID};
+
+fn source_chain() -> Blockchain {
+    let mut chain = Blockchain::new(DEFAULT_NETWORK_ID, ChainParams::new(1, 50.0).unwrap());
+    chain.mining_threads = 1;
+    chain.mine_pending_transactions("miner").unwrap();
+    chain.add_transaction("miner".to_string(), "alice".to_string(), 20.0).unwrap();
+    chain.mine_pending_transactions("miner").unwrap();
+    chain.add_transaction("alice".to_string(), "bob".to_string(), 5.0).unwrap();
+    chain.mine_pending_transactions("miner").unwrap();
+    chain.mine_pending_transactions("miner").unwrap();
+    chain
+}
+
+#[test]
+fn snapshot_bootstrap_keeps_balances_and_carries_on() {
+    let chain = source_chain();
+    let path = std::env::temp_dir().join(format!("rustchain-snapshot-{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    let snapshot = chain.save_snapshot(path, 3).unwrap();
+
+    let mut node = Blockchain::load_snapshot(path, &snapshot.block_hash).unwrap();
+    let _ = std::fs::remove_file(path);
+    assert_eq!(node.get_latest_block().hash, chain.get_block(3).unwrap().hash);
+    assert_eq!(node.pruned_height(), 4);
+    for address in ["miner", "alice", "bob"] {
+        assert_eq!(node.get_balance(address), chain.state_at(3).unwrap().balance(address), "{}", address);
+    }
+
+    // The snapshot node accepts the next block from the source and mines on top of it
+    node.submit_block(chain.get_block(4).unwrap().clone()).unwrap();
+    assert_eq!(node.get_latest_block().hash, chain.get_latest_block().hash);
+    node.mining_threads = 1;
+    node.add_transaction("bob".to_string(), "carol".to_string(), 1.0).unwrap();
+    node.mine_pending_transactions("miner").unwrap();
+    assert_eq!(node.get_balance("carol"), 1.0);
+    assert!(node.validate_chain().is_ok());
+}
+
+#[test]
+fn snapshot_must_match_the_trusted_block() {
+    let chain = source_chain();
+    let snapshot = chain.export_snapshot(3).unwrap();
+
+    let other = chain.get_block(2).unwrap().hash;
+    let error = Blockchain::from_snapshot(snapshot.clone(), &other).unwrap_err();
+    assert!(error.to_string().starts_with("Snapshot is for block"), "{}", error);
+
+    let mut truncated = snapshot.clone();
+    truncated.headers.pop();
+    let error = Blockchain::from_snapshot(truncated, &snapshot.block_hash).unwrap_err();
+    assert_eq!(error.to_string(), "Snapshot headers do not end at the snapshot block");
+
+    // Headers below the trusted block still have to link up and carry their work
+    let mut forged = snapshot.clone();
+    forged.headers[1].timestamp += 1;
+    assert!(Blockchain::from_snapshot(forged, &snapshot.block_hash).is_err());
+}
@@ -0,0 +1,183 @@
+// Chain files written by one version of the node and read back by it, in both encodings,
+// with the parameters that tripped earlier formats up.
+
+use std::path::PathBuf;
+
+use blockchain::storage::read_node_state;
+use blockchain::{Block, BlockStore, Blockchain, ChainFileFormat, ChainParams, DEFAULT_NETWORK_ID};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+// Layout of a version 2 chain file, fields in the order they were written
+#[derive(Serialize)]
+struct V2Body<'a> {
+    version: u32,
+    network_id: &'a str,
+    params: &'a ChainParams,
+    chain: &'a [Block],
+}
+
+#[derive(Serialize)]
+struct V2File<'a> {
+    #[serde(flatten)]
+    body: V2Body<'a>,
+    checksum: String,
+}
+
+// A fresh path under the system temp directory, unique to this test run
+fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rustchain-storage-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+// A fresh data directory under the system temp directory, unique to this test run
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = temp_path(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+// A short chain whose params pin two checkpoints
+fn checkpointed_chain() -> Blockchain {
+    let mut chain = Blockchain::new(DEFAULT_NETWORK_ID, ChainParams::new(1, 50.0).unwrap());
+    chain.mining_threads = 1;
+    chain.mine_pending_transactions("miner").unwrap();
+    chain.add_transaction("miner".to_string(), "alice".to_string(), 20.0).unwrap();
+    chain.mine_pending_transactions("miner").unwrap();
+    chain.add_transaction("alice".to_string(), "bob".to_string(), 5.0).unwrap();
+
+    let hashes: Vec<_> = chain.chain.iter().map(|block| block.hash).collect();
+    chain.params.checkpoints.insert(0, hashes[0]);
+    chain.params.checkpoints.insert(1, hashes[1]);
+    chain
+}
+
+fn assert_round_trip(format: ChainFileFormat) {
+    let chain = checkpointed_chain();
+    let path = temp_path(&format.to_string());
+    let path = path.to_str().unwrap();
+
+    chain.save_to_disk(path, true, format).unwrap();
+    chain.verify_saved(path).unwrap();
+
+    let loaded = Blockchain::load_from_disk(path, "other-network", &ChainParams::new(3, 1.0).unwrap()).unwrap();
+    assert_eq!(loaded.network_id, chain.network_id);
+    assert_eq!(loaded.params, chain.params);
+    assert_eq!(loaded.chain.len(), chain.chain.len());
+    assert_eq!(loaded.get_latest_block().hash, chain.get_latest_block().hash);
+    assert_eq!(loaded.pending_transactions.len(), 1);
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn json_chain_file_with_checkpoints_round_trips() {
+    assert_round_trip(ChainFileFormat::Json);
+}
+
+#[test]
+fn binary_chain_file_with_checkpoints_round_trips() {
+    assert_round_trip(ChainFileFormat::Binary);
+}
+
+#[test]
+fn tampered_chain_file_fails_its_checksum() {
+    let chain = checkpointed_chain();
+    let path = temp_path("tampered");
+    let path = path.to_str().unwrap();
+    chain.save_to_disk(path, false, ChainFileFormat::Json).unwrap();
+
+    let data = std::fs::read_to_string(path).unwrap();
+    std::fs::write(path, data.replace("\"mining_reward\":50.0", "\"mining_reward\":5000.0")).unwrap();
+    let error = Blockchain::load_from_disk(path, DEFAULT_NETWORK_ID, &chain.params).unwrap_err();
+    assert_eq!(error.to_string(), "Chain file checksum mismatch");
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn version_2_chain_file_with_checkpoints_still_loads() {
+    let chain = checkpointed_chain();
+
+    // Version 2 kept the contents beside the version and checksummed them re-serialized
+    let body = V2Body {
+        version: 2,
+        network_id: &chain.network_id,
+        params: &chain.params,
+        chain: &chain.chain,
+    };
+    let checksum = format!("{:x}", Sha256::digest(serde_json::to_vec(&body).unwrap()));
+    let file = V2File { body, checksum };
+
+    let path = temp_path("v2");
+    let path = path.to_str().unwrap();
+    std::fs::write(path, serde_json::to_vec(&file).unwrap()).unwrap();
+
+    let loaded = Blockchain::load_from_disk(path, DEFAULT_NETWORK_ID, &ChainParams::new(1, 50.0).unwrap()).unwrap();
+    assert_eq!(loaded.params.checkpoints, chain.params.checkpoints);
+    assert_eq!(loaded.get_latest_block().hash, chain.get_latest_block().hash);
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn block_store_round_trips_blocks_across_reopening() {
+    let chain = checkpointed_chain();
+    let dir = temp_dir("store");
+    {
+        let mut store = BlockStore::open(&dir).unwrap();
+        for block in &chain.chain {
+            store.append(block).unwrap();
+        }
+    }
+
+    let mut store = BlockStore::open(&dir).unwrap();
+    assert_eq!(store.len(), chain.chain.len() as u64);
+    for block in &chain.chain {
+        let stored = store.get(block.index).unwrap().unwrap();
+        assert_eq!(stored.hash, block.hash);
+        let ids = |block: &Block| block.transactions.iter().map(|transaction| transaction.id()).collect::<Vec<_>>();
+        assert_eq!(ids(&stored), ids(block));
+        assert_eq!(store.get_by_hash(&block.hash).unwrap().unwrap().index, block.index);
+    }
+    assert!(store.get(chain.chain.len() as u64).unwrap().is_none());
+
+    store.truncate(1).unwrap();
+    drop(store);
+    let store = BlockStore::open(&dir).unwrap();
+    assert_eq!(store.len(), 1);
+    assert!(store.get_by_hash(&chain.chain[1].hash).unwrap().is_none());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn pruned_data_directory_reopens_compacted() {
+    let dir = temp_dir("pruned");
+    let params = ChainParams::new(1, 50.0).unwrap();
+    let mut chain = Blockchain::open(&dir, DEFAULT_NETWORK_ID, params.clone()).unwrap();
+    chain.mining_threads = 1;
+    chain.mine_pending_transactions("miner").unwrap();
+    for amount in 1..=5 {
+        chain.add_transaction("miner".to_string(), "alice".to_string(), amount as f32).unwrap();
+        chain.mine_pending_transactions("miner").unwrap();
+    }
+    let tip = chain.get_latest_block().hash;
+
+    assert_eq!(chain.prune(2).unwrap(), 5);
+    assert_eq!(chain.pruned_height(), 5);
+    assert_eq!(read_node_state(&dir).unwrap().unwrap().compacted, 5);
+    let data_len = std::fs::metadata(dir.join("blocks.dat")).unwrap().len();
+    drop(chain);
+
+    // Bodies below the pruned height are gone, but balances and the tip are not
+    let mut chain = Blockchain::open(&dir, DEFAULT_NETWORK_ID, params).unwrap();
+    assert_eq!(chain.get_latest_block().hash, tip);
+    assert_eq!(chain.pruned_height(), 5);
+    assert!(chain.get_block(3).unwrap().transactions.is_empty());
+    assert_eq!(chain.get_balance("alice"), 15.0);
+    assert!(chain.validate_chain().is_ok());
+    assert_eq!(std::fs::metadata(dir.join("blocks.dat")).unwrap().len(), data_len);
+
+    chain.mining_threads = 1;
+    chain.mine_pending_transactions("miner").unwrap();
+    assert_eq!(chain.get_block(6).unwrap().transactions.len(), 2);
+    let _ = std::fs::remove_dir_all(&dir);
+}